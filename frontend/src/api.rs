@@ -0,0 +1,81 @@
+//! Typed calls to the `/api` routes of the server.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use gloo_net::http::{Request, Response};
+use serde::de::DeserializeOwned;
+use shared::api::{
+    post_path, ContactInvalid, ContactRequest, ContactSent, ContactToken, Hello, Post, Problem,
+};
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// no answer, or one that is not the expected JSON
+    Network(gloo_net::Error),
+    /// an answer with an unexpected status, `text` being the detail of its
+    /// problem if it has one
+    Status { status: u16, text: String },
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(err) => write!(f, "{err}"),
+            ApiError::Status { status, text } => write!(f, "{status} ({text})"),
+        }
+    }
+}
+
+impl From<gloo_net::Error> for ApiError {
+    fn from(err: gloo_net::Error) -> Self {
+        ApiError::Network(err)
+    }
+}
+
+async fn json<T: DeserializeOwned>(resp: Response) -> Result<T, ApiError> {
+    if !resp.ok() {
+        let text = match resp.json::<Problem>().await {
+            Ok(problem) => problem.detail.unwrap_or(problem.title),
+            Err(_) => resp.status_text(),
+        };
+        return Err(ApiError::Status {
+            status: resp.status(),
+            text,
+        });
+    }
+    Ok(resp.json().await?)
+}
+
+/// `GET /api/hello/`
+pub async fn hello() -> Result<Hello, ApiError> {
+    json(Request::get("/api/hello/").send().await?).await
+}
+
+/// `GET /api/posts/{slug}`
+pub async fn post(slug: &str) -> Result<Post, ApiError> {
+    json(Request::get(&post_path(slug)).send().await?).await
+}
+
+/// How the server took a [`ContactRequest`].
+pub enum ContactOutcome {
+    Sent,
+    /// messages for people, keyed by field name
+    Invalid(BTreeMap<String, String>),
+}
+
+/// `GET /api/contact-token`
+pub async fn contact_token() -> Result<ContactToken, ApiError> {
+    json(Request::get("/api/contact-token").send().await?).await
+}
+
+/// `POST /api/contact`
+pub async fn contact(request: &ContactRequest) -> Result<ContactOutcome, ApiError> {
+    let resp = Request::post("/api/contact").json(request)?.send().await?;
+    if resp.status() == 422 {
+        let invalid: ContactInvalid = resp.json().await?;
+        return Ok(ContactOutcome::Invalid(invalid.errors));
+    }
+    json::<ContactSent>(resp).await?;
+    Ok(ContactOutcome::Sent)
+}
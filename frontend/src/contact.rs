@@ -0,0 +1,130 @@
+//! The contact form, posting to `/api/contact`.
+
+use std::collections::BTreeMap;
+
+use shared::api::ContactRequest;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+use yew::prelude::*;
+
+use crate::api::{self, ApiError, ContactOutcome};
+
+#[derive(Clone, PartialEq)]
+enum Status {
+    Editing,
+    Sending,
+    Sent,
+    Failed(String),
+}
+
+#[function_component(ContactForm)]
+pub fn contact_form() -> Html {
+    let fields = use_state(ContactRequest::default);
+    let errors = use_state(BTreeMap::<String, String>::new);
+    let status = use_state(|| Status::Editing);
+    // says when the form was shown, the server treats very quick sends as spam
+    let token = use_state(String::new);
+    {
+        let token = token.clone();
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                match api::contact_token().await {
+                    Ok(issued) => token.set(issued.token),
+                    // sending without one tells to reload
+                    Err(err) => log::warn!("no contact form token: {err}"),
+                }
+            });
+
+            || {}
+        });
+    }
+
+    let oninput = |set: fn(&mut ContactRequest, String)| {
+        let fields = fields.clone();
+        Callback::from(move |e: InputEvent| {
+            let value = match e.target_dyn_into::<HtmlTextAreaElement>() {
+                Some(area) => area.value(),
+                None => e.target_unchecked_into::<HtmlInputElement>().value(),
+            };
+            let mut next = (*fields).clone();
+            set(&mut next, value);
+            fields.set(next);
+        })
+    };
+
+    let onsubmit = {
+        let (fields, errors, status) = (fields.clone(), errors.clone(), status.clone());
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let mut body = (*fields).clone();
+            body.form_token = (*token).clone();
+            let (errors, status) = (errors.clone(), status.clone());
+            status.set(Status::Sending);
+            spawn_local(async move {
+                match api::contact(&body).await {
+                    Ok(ContactOutcome::Sent) => {
+                        errors.set(BTreeMap::new());
+                        status.set(Status::Sent);
+                    }
+                    Ok(ContactOutcome::Invalid(invalid)) => {
+                        errors.set(invalid);
+                        status.set(Status::Editing);
+                    }
+                    Err(ApiError::Status { status: 429, .. }) => status.set(Status::Failed(
+                        "You sent a lot of messages already, please try again later.".into(),
+                    )),
+                    Err(err) => status.set(Status::Failed(format!(
+                        "The message could not be sent ({err})."
+                    ))),
+                }
+            });
+        })
+    };
+
+    if *status == Status::Sent {
+        return html! {
+            <div style = "width: min(32rem, 90vw); margin-left:auto;margin-right:auto;">
+                <h1>{ "Thanks!" }</h1>
+                <p>{ "Your message is on its way." }</p>
+            </div>
+        };
+    }
+
+    let error = |field: &str| match errors.get(field) {
+        Some(error) => html! { <div style = "color:rgb(241,76,76);">{ error }</div> },
+        None => html! {},
+    };
+    let sending = *status == Status::Sending;
+
+    html! {
+        <form {onsubmit} style = "width: min(32rem, 90vw); margin-left:auto;margin-right:auto;display:flex;flex-direction:column;gap:0.5rem;">
+            <h1>{ "Contact" }</h1>
+            <label for = "contact-name">{ "Name" }</label>
+            <input id = "contact-name" value = {fields.name.clone()}
+                oninput = {oninput(|fields, value| fields.name = value)} />
+            { error("name") }
+            <label for = "contact-email">{ "Email" }</label>
+            <input id = "contact-email" type = "email" value = {fields.email.clone()}
+                oninput = {oninput(|fields, value| fields.email = value)} />
+            { error("email") }
+            <label for = "contact-message">{ "Message" }</label>
+            <textarea id = "contact-message" rows = "8" value = {fields.message.clone()}
+                oninput = {oninput(|fields, value| fields.message = value)} />
+            { error("message") }
+            // off screen rather than `display: none`, which bots look for
+            <div aria-hidden = "true" style = "position:absolute;left:-10000px;">
+                <label for = "contact-website">{ "Leave this empty" }</label>
+                <input id = "contact-website" tabindex = "-1" autocomplete = "off"
+                    value = {fields.website.clone()}
+                    oninput = {oninput(|fields, value| fields.website = value)} />
+            </div>
+            { error("form") }
+            if let Status::Failed(reason) = &*status {
+                <div style = "color:rgb(241,76,76);">{ reason }</div>
+            }
+            <button type = "submit" disabled = {sending}>
+                { if sending { "Sending..." } else { "Send" } }
+            </button>
+        </form>
+    }
+}
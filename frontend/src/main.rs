@@ -1,19 +1,13 @@
+mod api;
+mod contact;
 mod wgpu_canvas;
 mod wgpu_context;
 
-use gloo_net::http::Request;
+use shared::routes::Route;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
-#[derive(Clone, Routable, PartialEq)]
-enum Route {
-    #[at("/")]
-    Home,
-    #[at("/hello-server")]
-    HelloServer,
-}
-
 fn switch(routes: Route) -> Html {
     match routes {
         Route::Home => html! {
@@ -25,6 +19,56 @@ fn switch(routes: Route) -> Html {
             </>
         },
         Route::HelloServer => html! { <HelloServer/> },
+        Route::Contact => html! { <contact::ContactForm/> },
+        Route::Post { slug } => html! { <PostPage {slug}/> },
+    }
+}
+
+#[derive(PartialEq, Properties)]
+struct PostPageProps {
+    slug: String,
+}
+
+#[function_component(PostPage)]
+fn post_page(props: &PostPageProps) -> Html {
+    let data = use_state(|| None);
+
+    // Request `/api/posts/{slug}` whenever the slug changes
+    {
+        let data = data.clone();
+        use_effect_with(props.slug.clone(), move |slug| {
+            let slug = slug.clone();
+            spawn_local(async move {
+                let result = api::post(&slug)
+                    .await
+                    .map_err(|err| format!("Error fetching post {err}"));
+                data.set(Some(result));
+            });
+
+            || {}
+        });
+    }
+
+    match data.as_ref() {
+        None => {
+            html! {
+                <div>{"loading..."}</div>
+            }
+        }
+        Some(Ok(post)) => {
+            html! {
+                <article style = "width: min(48rem, 90vw); margin-left:auto;margin-right:auto;">
+                    <h1>{ &post.meta.title }</h1>
+                    <time>{ post.meta.date.to_string() }</time>
+                    { Html::from_html_unchecked(post.html.clone().into()) }
+                </article>
+            }
+        }
+        Some(Err(err)) => {
+            html! {
+                <div>{"Error requesting post from server: "}{err}</div>
+            }
+        }
     }
 }
 
@@ -38,18 +82,10 @@ fn hello_server() -> Html {
         use_effect(move || {
             if data.is_none() {
                 spawn_local(async move {
-                    let resp = Request::get("/api/hello/").send().await.unwrap();
-                    let result = {
-                        if !resp.ok() {
-                            Err(format!(
-                                "Error fetching data {} ({})",
-                                resp.status(),
-                                resp.status_text()
-                            ))
-                        } else {
-                            resp.text().await.map_err(|err| err.to_string())
-                        }
-                    };
+                    let result = api::hello()
+                        .await
+                        .map(|hello| hello.message)
+                        .map_err(|err| format!("Error fetching data {err}"));
                     data.set(Some(result));
                 });
             }
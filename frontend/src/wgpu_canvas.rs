@@ -52,7 +52,7 @@ pub struct WgpuCanvasProps {}
 
 pub enum WgpuCanvasMsg<'a> {
     Initializing,
-    Initialized(WgpuContext<'a>),
+    Initialized(Box<WgpuContext<'a>>),
     Control(ControlMsg),
     Update,
 }
@@ -72,7 +72,9 @@ impl Component for WgpuCanvas<'static> {
 
     fn create(ctx: &Context<Self>) -> Self {
         let canvas = NodeRef::default();
-        let context_cb: Callback<WgpuContext> = ctx.link().callback(WgpuCanvasMsg::Initialized);
+        let context_cb: Callback<WgpuContext> = ctx
+            .link()
+            .callback(|context| WgpuCanvasMsg::Initialized(Box::new(context)));
 
         ctx.link().callback(|_| WgpuCanvasMsg::Update).emit(());
 
@@ -102,7 +104,7 @@ impl Component for WgpuCanvas<'static> {
             }
             WgpuCanvasMsg::Initialized(wgpu_state) => {
                 log::info!("Initialized");
-                self.context = Some(wgpu_state);
+                self.context = Some(*wgpu_state);
 
                 let handle = {
                     let link = ctx.link().clone();
@@ -1,16 +1,18 @@
-mod lambda;
-
-use server::setup_app;
-use vercel_runtime::{process_request, process_response, run_service, Error, ServiceBuilder};
+use clap::Parser;
+use server::{lambda, setup_app, Config, Opt, Shutdown};
+use vercel_runtime::{process_request, run_service, Error, ServiceBuilder};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let app = setup_app("./dist".to_owned()).await;
+    // the runtime passes no arguments, so settings come from the
+    // environment and server.toml
+    let config = Config::load(Opt::parse_from(["vercel"]))?;
+    server::logging::init(&config);
+    let app = setup_app(&config, &Shutdown::default()).await?;
 
     let handler = ServiceBuilder::new()
         .map_request(process_request)
-        .map_response(process_response)
-        .layer(lambda::LambdaLayer::default())
+        .layer(lambda::layer(&config))
         .service(app);
 
     run_service(handler).await
@@ -0,0 +1,61 @@
+//! Embeds build info for `/api/version`.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // vercel builds from a checkout without `.git`, but tells us the commit
+    println!("cargo:rerun-if-env-changed=VERCEL_GIT_COMMIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // a path that does not exist counts as changed on every build, and
+    // vercel's checkout has no `.git`
+    if Path::new("../.git/HEAD").is_file() {
+        // commits and checkouts; not `.git/index`, which `git status` below
+        // rewrites, so it would change on every build
+        for path in ["../.git/HEAD", "../.git/refs"] {
+            if Path::new(path).exists() {
+                println!("cargo:rerun-if-changed={path}");
+            }
+        }
+        // edits that make the tree dirty, so the flag and the time are those
+        // of the sources the binary is built from
+        for path in ["src", "api", "Cargo.toml", "../shared"] {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    let sha = std::env::var("VERCEL_GIT_COMMIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(git_sha)
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=BUILD_GIT_SHA={sha}");
+
+    // reproducible builds pin the timestamp
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=BUILD_UNIX_TIME={built_at}");
+}
+
+fn git_sha() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let sha = String::from_utf8(output.stdout).ok()?.trim().to_owned();
+
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.is_empty());
+    Some(if dirty { format!("{sha}-dirty") } else { sha })
+}
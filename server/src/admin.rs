@@ -0,0 +1,192 @@
+//! Endpoints for operating a running server, behind `--admin-token`.
+//!
+//! `GET /api/admin/log-filter` answers the current log filter and `PUT` with
+//! a directive like `info,server::spa=debug` as the body replaces it.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use shared::api::Problem;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use crate::auth;
+use crate::error::ApiError;
+use crate::logging::LogFilter;
+
+/// The endpoints, changing `filter`, the one of [`crate::logging::filter`] outside
+/// of tests.
+pub fn router(token: &str, filter: Option<&'static LogFilter>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(get_filter, put_filter))
+        .with_state(AdminState {
+            token: Arc::from(token),
+            filter,
+        })
+}
+
+#[derive(Clone)]
+struct AdminState {
+    token: Arc<str>,
+    filter: Option<&'static LogFilter>,
+}
+
+/// The current log filter.
+#[utoipa::path(
+    get,
+    path = "/api/admin/log-filter",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = OK, description = "The filter directive", body = String, content_type = "text/plain"),
+        (status = UNAUTHORIZED, description = "The token is missing or wrong", body = Problem, content_type = "application/problem+json"),
+        (status = SERVICE_UNAVAILABLE, description = "Logging was not set up by this server", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn get_filter(State(state): State<AdminState>, headers: HeaderMap) -> Response {
+    if !auth::has_bearer(&headers, &state.token) {
+        return auth::unauthorized();
+    }
+    match state.filter {
+        Some(filter) => format!("{}\n", filter.current()).into_response(),
+        None => not_reloadable(),
+    }
+}
+
+/// Replaces the log filter until the next SIGHUP or restart.
+#[utoipa::path(
+    put,
+    path = "/api/admin/log-filter",
+    tag = "admin",
+    security(("admin_token" = [])),
+    request_body(content = String, description = "A directive like `info,server::spa=debug`", content_type = "text/plain"),
+    responses(
+        (status = OK, description = "The new filter directive", body = String, content_type = "text/plain"),
+        (status = BAD_REQUEST, description = "The directive is invalid", body = Problem, content_type = "application/problem+json"),
+        (status = UNAUTHORIZED, description = "The token is missing or wrong", body = Problem, content_type = "application/problem+json"),
+        (status = SERVICE_UNAVAILABLE, description = "Logging was not set up by this server", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn put_filter(State(state): State<AdminState>, headers: HeaderMap, body: String) -> Response {
+    if !auth::has_bearer(&headers, &state.token) {
+        return auth::unauthorized();
+    }
+    let Some(filter) = state.filter else {
+        return not_reloadable();
+    };
+    let directive = body.trim();
+    match filter.set(directive) {
+        Ok(()) => {
+            log::info!("log filter set to {directive:?}");
+            format!("{directive}\n").into_response()
+        }
+        Err(err) => ApiError::new(StatusCode::BAD_REQUEST)
+            .detail(err.to_string())
+            .into_response(),
+    }
+}
+
+fn not_reloadable() -> Response {
+    ApiError::new(StatusCode::SERVICE_UNAVAILABLE)
+        .detail("Logging was not set up by this server.")
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::Router;
+    use tower::ServiceExt;
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    async fn call(
+        app: &Router,
+        req: axum::http::request::Builder,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let req = req.body(Body::from(body.to_owned())).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.into()).unwrap())
+    }
+
+    fn get() -> axum::http::request::Builder {
+        Request::get("/api/admin/log-filter")
+    }
+
+    fn put() -> axum::http::request::Builder {
+        Request::put("/api/admin/log-filter")
+    }
+
+    #[tokio::test]
+    async fn log_filter() {
+        let (layer, filter) = LogFilter::new("info".to_owned());
+        let filter: &'static LogFilter = Box::leak(Box::new(filter));
+        let subscriber = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+        let debug_enabled = || {
+            tracing::dispatcher::with_default(
+                &subscriber,
+                || tracing::enabled!(target: "server::spa", Level::DEBUG),
+            )
+        };
+        let (app, _) = router("secret", Some(filter)).split_for_parts();
+        let auth = ("authorization", "Bearer secret");
+
+        for req in [
+            get(),
+            get().header("authorization", "Bearer wrong"),
+            put(),
+            put().header("authorization", "secret"),
+        ] {
+            let (status, _) = call(&app, req, "debug").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(filter.current(), "info");
+
+        assert_eq!(
+            call(&app, get().header(auth.0, auth.1), "").await,
+            (StatusCode::OK, "info\n".to_owned())
+        );
+        assert!(!debug_enabled());
+
+        let directive = "info,server::spa=debug";
+        let (status, body) = call(
+            &app,
+            put().header(auth.0, auth.1),
+            &format!("{directive}\n"),
+        )
+        .await;
+        assert_eq!(
+            (status, body.as_str()),
+            (StatusCode::OK, "info,server::spa=debug\n")
+        );
+        assert_eq!(filter.current(), directive);
+        assert!(debug_enabled());
+        assert_eq!(
+            call(&app, get().header(auth.0, auth.1), "").await,
+            (StatusCode::OK, format!("{directive}\n"))
+        );
+
+        let (status, body) = call(&app, put().header(auth.0, auth.1), "server=loud").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("\"detail\""), "{body}");
+        assert_eq!(filter.current(), directive);
+        assert!(debug_enabled());
+    }
+
+    #[tokio::test]
+    async fn without_logging() {
+        let (app, _) = router("secret", None).split_for_parts();
+        let (status, _) = call(&app, get().header("authorization", "Bearer secret"), "").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,28 @@
+//! Bearer tokens for the endpoints that are not for everyone.
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use crate::error::ApiError;
+
+/// Whether `headers` carry `Authorization: Bearer <token>`.
+pub(crate) fn has_bearer(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+pub(crate) fn unauthorized() -> Response {
+    (
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        ApiError::new(StatusCode::UNAUTHORIZED),
+    )
+        .into_response()
+}
+
+/// Compare without leaking how much of the token was right through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
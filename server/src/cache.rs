@@ -0,0 +1,249 @@
+//! `Cache-Control` and `ETag` policy for files from the static dir.
+//!
+//! Trunk puts a content hash in the names of the js and wasm it emits, so those
+//! never change and can be cached for good. `index.html` keeps its name across
+//! builds and points at the current hashed files, so browsers must revalidate
+//! it on every load; a strong `ETag` keeps that revalidation cheap. Unless it
+//! carries a CSP nonce, which makes every response new: then it is not stored.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::spa::Nonced;
+
+/// The shortest run of hex digits that counts as a content hash in a file
+/// name. Trunk prints its hashes without padding, so allow for leading zeros.
+const MIN_HASH_LEN: usize = 12;
+
+#[derive(Clone, Copy, Debug)]
+pub struct CachePolicy {
+    /// `max-age` in seconds for content-hashed files
+    pub hashed_max_age: u64,
+}
+
+/// Middleware applying `policy` to successful static responses.
+pub async fn apply(State(policy): State<CachePolicy>, req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_owned();
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+    // a HEAD response has no body to hash
+    let is_head = req.method() == Method::HEAD;
+    let res = next.run(req).await;
+    if res.status() != StatusCode::OK {
+        return res;
+    }
+
+    if is_hashed(&path) {
+        let value = format!("public, max-age={}, immutable", policy.hashed_max_age);
+        let mut res = res;
+        res.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_str(&value).expect("cache-control is ascii"),
+        );
+        return res;
+    }
+
+    if res.extensions().get::<Nonced>().is_some() {
+        // no ETag either, a 304 would come with a CSP for a new nonce
+        let mut res = res;
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return res;
+    }
+
+    if is_head || !is_html(&path, res.headers()) {
+        let mut res = res;
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::error!("failed to buffer {path} for etag: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let etag = strong_etag(&bytes);
+    parts
+        .headers
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    parts.headers.insert(header::ETAG, etag.clone());
+
+    if if_none_match.is_some_and(|tags| etag_matches(&tags, &etag)) {
+        let mut not_modified = Response::new(Body::empty());
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+        for name in [header::CACHE_CONTROL, header::ETAG, header::VARY] {
+            if let Some(value) = parts.headers.get(&name) {
+                not_modified.headers_mut().insert(name, value.clone());
+            }
+        }
+        return not_modified;
+    }
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Whether the last path segment carries a content hash, like trunk's
+/// `frontend-6a1f0b2c9d3e4f57.js` or `frontend-6a1f0b2c9d3e4f57_bg.wasm`.
+pub fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let stem = name.split('.').next().unwrap_or(name);
+    let Some((_, suffix)) = stem.rsplit_once('-') else {
+        return false;
+    };
+    let hash_len = suffix.bytes().take_while(|b| b.is_ascii_hexdigit()).count();
+    hash_len >= MIN_HASH_LEN && matches!(suffix.as_bytes().get(hash_len), None | Some(b'_'))
+}
+
+fn is_html(path: &str, headers: &HeaderMap) -> bool {
+    match headers.get(header::CONTENT_TYPE) {
+        Some(content_type) => content_type.as_bytes().starts_with(b"text/html"),
+        None => {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            !name.contains('.') || name.ends_with(".html")
+        }
+    }
+}
+
+fn strong_etag(bytes: &[u8]) -> HeaderValue {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    HeaderValue::from_str(&format!("\"{:016x}\"", hasher.finish())).expect("etag is ascii")
+}
+
+/// `If-None-Match` uses the weak comparison, so `W/` prefixes are ignored.
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(tags) = if_none_match.to_str() else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default();
+    tags.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get_service;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::spa::SpaFallback;
+
+    #[test]
+    fn hashed_names() {
+        for path in [
+            "/frontend-6a1f0b2c9d3e4f57.js",
+            "/frontend-6a1f0b2c9d3e4f57_bg.wasm",
+            "/sub/frontend-6a1f0b2c9d3e4f57.js",
+            "/style-00a1f0b2c9d3e4f5.css",
+        ] {
+            assert!(is_hashed(path), "{path}");
+        }
+        for path in [
+            "/index.html",
+            "/style.css",
+            "/frontend.js",
+            "/frontend-6a1f0b2c9d.js",
+            "/frontend-6a1f0b2c9d3e4f57x.js",
+            "/my-favourite-photograph.png",
+        ] {
+            assert!(!is_hashed(path), "{path}");
+        }
+    }
+
+    const MAX_AGE: u64 = 600;
+
+    fn app() -> (Router, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<html><body></body></html>").unwrap();
+        std::fs::write(dir.path().join("frontend-6a1f0b2c9d3e4f57_bg.wasm"), "wasm").unwrap();
+        std::fs::write(dir.path().join("style.css"), "body {}").unwrap();
+        let policy = CachePolicy {
+            hashed_max_age: MAX_AGE,
+        };
+        let spa = SpaFallback::new(dir.path()).unwrap();
+        let app = Router::new().fallback_service(
+            get_service(spa).layer(middleware::from_fn_with_state(policy, apply)),
+        );
+        (app, dir)
+    }
+
+    async fn get(app: &Router, path: &str, if_none_match: Option<&str>) -> Response {
+        let mut req = Request::get(path);
+        if let Some(tags) = if_none_match {
+            req = req.header(header::IF_NONE_MATCH, tags);
+        }
+        app.clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn cache_control(res: &Response) -> &str {
+        res.headers()[header::CACHE_CONTROL].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn hashed_files_are_immutable() {
+        let (app, _dir) = app();
+        let res = get(&app, "/frontend-6a1f0b2c9d3e4f57_bg.wasm", None).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(cache_control(&res), "public, max-age=600, immutable");
+        assert!(res.headers().get(header::ETAG).is_none());
+    }
+
+    #[tokio::test]
+    async fn other_files_are_revalidated() {
+        let (app, _dir) = app();
+        let res = get(&app, "/style.css", None).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(cache_control(&res), "no-cache");
+    }
+
+    #[tokio::test]
+    async fn index_has_etag() {
+        let (app, _dir) = app();
+        for path in ["/", "/index.html"] {
+            let res = get(&app, path, None).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(cache_control(&res), "no-cache");
+            assert!(res.headers().contains_key(header::ETAG), "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn if_none_match() {
+        let (app, _dir) = app();
+        let res = get(&app, "/index.html", None).await;
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+
+        for tags in [
+            etag.clone(),
+            format!("W/{etag}"),
+            "*".to_owned(),
+            format!("\"other\", {etag}"),
+        ] {
+            let res = get(&app, "/index.html", Some(&tags)).await;
+            assert_eq!(res.status(), StatusCode::NOT_MODIFIED, "{tags}");
+            assert_eq!(res.headers()[header::ETAG], etag.as_str());
+            assert_eq!(cache_control(&res), "no-cache");
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(body.is_empty());
+        }
+
+        let res = get(&app, "/index.html", Some("\"other\"")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
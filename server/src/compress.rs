@@ -0,0 +1,170 @@
+//! Writes `.br` and `.gz` siblings next to every file in the static dir, so
+//! the fallback service can send them to clients that accept them.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+
+/// Extensions of formats that are already compressed and would not shrink.
+const INCOMPRESSIBLE: &[&str] = &[
+    "br", "gz", "zst", "png", "jpg", "jpeg", "gif", "webp", "avif", "woff", "woff2", "zip", "mp3",
+    "mp4", "webm",
+];
+
+#[derive(Default, Debug)]
+pub struct CompressStats {
+    pub written: usize,
+    pub up_to_date: usize,
+    pub skipped: usize,
+}
+
+/// Compress every file below `dir`. Siblings that are newer than their source
+/// are left alone, and ones that would be no smaller than the source are
+/// removed instead of written.
+pub fn compress_dir(dir: &Path) -> io::Result<CompressStats> {
+    let mut stats = CompressStats::default();
+    let mut pending = vec![dir.to_owned()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if is_compressible(&path) {
+                compress_file(&path, &mut stats)?;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+fn is_compressible(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => !INCOMPRESSIBLE.contains(&ext.to_ascii_lowercase().as_str()),
+        None => true,
+    }
+}
+
+fn compress_file(path: &Path, stats: &mut CompressStats) -> io::Result<()> {
+    let modified = fs::metadata(path)?.modified()?;
+    let mut source = None;
+
+    for (suffix, encode) in [
+        ("br", brotli as fn(&[u8]) -> io::Result<Vec<u8>>),
+        ("gz", gzip),
+    ] {
+        let sibling = sibling(path, suffix);
+        let fresh = fs::metadata(&sibling)
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|sibling_modified| sibling_modified >= modified);
+        if fresh {
+            stats.up_to_date += 1;
+            continue;
+        }
+
+        let source = match &source {
+            Some(source) => source,
+            None => source.insert(fs::read(path)?),
+        };
+        let encoded = encode(source)?;
+        if encoded.len() < source.len() {
+            fs::write(&sibling, encoded)?;
+            stats.written += 1;
+        } else {
+            match fs::remove_file(&sibling) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+            stats.skipped += 1;
+        }
+    }
+    Ok(())
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn brotli(source: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 11, 22);
+        writer.write_all(source)?;
+    }
+    Ok(out)
+}
+
+fn gzip(source: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(source)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    /// Text that compresses well.
+    fn text() -> String {
+        "all work and no play makes jack a dull boy\n".repeat(100)
+    }
+
+    #[test]
+    fn writes_siblings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/app.js"), text()).unwrap();
+
+        let stats = compress_dir(dir.path()).unwrap();
+        assert_eq!(stats.written, 2);
+        assert!(dir.path().join("sub/app.js.br").is_file());
+        assert!(dir.path().join("sub/app.js.gz").is_file());
+
+        let stats = compress_dir(dir.path()).unwrap();
+        assert_eq!((stats.written, stats.up_to_date), (0, 2));
+    }
+
+    #[test]
+    fn skips_compressed_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["img.png", "font.woff2", "app.js.gz", "app.js.br", "IMG.JPG"] {
+            std::fs::write(dir.path().join(name), text()).unwrap();
+        }
+
+        let stats = compress_dir(dir.path()).unwrap();
+        assert_eq!(stats.written + stats.skipped + stats.up_to_date, 0);
+        assert!(!dir.path().join("img.png.gz").exists());
+        assert!(!dir.path().join("app.js.gz.gz").exists());
+    }
+
+    #[test]
+    fn removes_siblings_that_are_not_smaller() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("tiny.txt");
+        std::fs::write(&source, "a").unwrap();
+        // left over from when the file was larger, and older than it
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for suffix in ["br", "gz"] {
+            let stale = sibling(&source, suffix);
+            std::fs::write(&stale, "stale").unwrap();
+            File::options()
+                .write(true)
+                .open(&stale)
+                .unwrap()
+                .set_modified(an_hour_ago)
+                .unwrap();
+        }
+
+        let stats = compress_dir(dir.path()).unwrap();
+        assert_eq!((stats.written, stats.skipped), (0, 2));
+        assert!(!sibling(&source, "br").exists());
+        assert!(!sibling(&source, "gz").exists());
+    }
+}
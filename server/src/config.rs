@@ -0,0 +1,776 @@
+//! Server configuration, layered from highest to lowest priority:
+//!
+//! 1. command line flags
+//! 2. `SERVER_*` environment variables
+//! 3. the config file, `server.toml` unless `--config` names another
+//! 4. built in defaults
+//!
+//! [`Opt`] is both the command line interface and the schema of the config
+//! file, with every setting optional. [`Config::load`] merges the layers and
+//! validates the result, so the rest of the server only sees sane values.
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use axum::http::Uri;
+use clap::{Parser, Subcommand};
+use lettre::message::Mailbox;
+use serde::Deserialize;
+
+use crate::contact::{ContactConfig, DeliveryConfig};
+use crate::logging::LogFormat;
+use crate::metrics::MetricsConfig;
+use crate::ratelimit::{IpNet, RateLimitRule};
+use crate::security::{SecurityHeaders, SecurityHeadersOpt};
+use crate::tls::TlsConfig;
+
+const DEFAULT_CONFIG: &str = "server.toml";
+
+// Setup the command line interface with clap.
+#[derive(Parser, Deserialize, Debug, Default)]
+#[clap(name = "server", about = "A server for our wasm project!")]
+#[serde(default, deny_unknown_fields)]
+pub struct Opt {
+    /// set the config file [default: server.toml, if present]
+    #[clap(short = 'c', long = "config", env = "SERVER_CONFIG")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// set the log level [default: debug]
+    #[clap(short = 'l', long = "log", env = "SERVER_LOG")]
+    pub log_level: Option<String>,
+
+    /// set the log output format [default: text]
+    #[clap(long = "log-format", env = "SERVER_LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// set the listen addr [default: ::1]
+    #[clap(short = 'a', long = "addr", env = "SERVER_ADDR")]
+    pub addr: Option<String>,
+
+    /// set the listen port [default: 8080]
+    #[clap(short = 'p', long = "port", env = "SERVER_PORT")]
+    pub port: Option<u16>,
+
+    /// set the directory where static files are to be found [default: ./dist]
+    #[clap(long = "static-dir", env = "SERVER_STATIC_DIR")]
+    pub static_dir: Option<PathBuf>,
+
+    /// set the directory where markdown posts are to be found [default: ./content]
+    #[clap(long = "content-dir", env = "SERVER_CONTENT_DIR")]
+    pub content_dir: Option<PathBuf>,
+
+    /// set the public url of the site, used for absolute links in feeds
+    /// [default: from addr and port, like http://localhost:8080]
+    #[clap(long = "base-url", env = "SERVER_BASE_URL")]
+    pub base_url: Option<String>,
+
+    /// set a file to serve as /robots.txt instead of the generated one
+    #[clap(long = "robots-txt", env = "SERVER_ROBOTS_TXT")]
+    pub robots_txt: Option<PathBuf>,
+
+    /// set the max-age in seconds for content-hashed static files [default: 31536000]
+    #[clap(long = "hashed-max-age", env = "SERVER_HASHED_MAX_AGE")]
+    pub hashed_max_age: Option<u64>,
+
+    /// do not send cache-control or etag headers for static files
+    #[clap(long = "no-cache-headers", env = "SERVER_NO_CACHE_HEADERS",
+        num_args = 0..=1, default_missing_value = "true")]
+    pub no_cache_headers: Option<bool>,
+
+    /// serve HTTPS with this PEM certificate chain, reloaded when it changes
+    #[clap(long = "tls-cert", env = "SERVER_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// set the PEM private key for --tls-cert
+    #[clap(long = "tls-key", env = "SERVER_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// also listen for plain HTTP on this port and redirect it to HTTPS
+    #[clap(long = "http-redirect-port", env = "SERVER_HTTP_REDIRECT_PORT")]
+    pub http_redirect_port: Option<u16>,
+
+    /// set how many seconds in-flight requests get to finish after SIGINT or
+    /// SIGTERM [default: 30]
+    #[clap(long = "shutdown-timeout", env = "SERVER_SHUTDOWN_TIMEOUT")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// set the path of the Prometheus metrics [default: /metrics]
+    #[clap(long = "metrics-path", env = "SERVER_METRICS_PATH")]
+    pub metrics_path: Option<String>,
+
+    /// require `Authorization: Bearer <token>` for the metrics
+    #[clap(
+        long = "metrics-token",
+        env = "SERVER_METRICS_TOKEN",
+        hide_env_values = true
+    )]
+    pub metrics_token: Option<String>,
+
+    /// do not record or serve metrics
+    #[clap(long = "no-metrics", env = "SERVER_NO_METRICS",
+        num_args = 0..=1, default_missing_value = "true")]
+    pub no_metrics: Option<bool>,
+
+    /// enable the /api/admin endpoints for `Authorization: Bearer <token>`
+    #[clap(
+        long = "admin-token",
+        env = "SERVER_ADMIN_TOKEN",
+        hide_env_values = true
+    )]
+    pub admin_token: Option<String>,
+
+    /// do not serve the API documentation page at /api/docs, the OpenAPI
+    /// document at /api/openapi.json stays
+    #[clap(long = "no-api-docs", env = "SERVER_NO_API_DOCS",
+        num_args = 0..=1, default_missing_value = "true")]
+    pub no_api_docs: Option<bool>,
+
+    /// set the proxies, as addresses or CIDR ranges, whose X-Forwarded-For is
+    /// believed when telling clients apart
+    #[clap(
+        long = "trusted-proxy",
+        env = "SERVER_TRUSTED_PROXIES",
+        value_delimiter = ','
+    )]
+    pub trusted_proxies: Option<Vec<String>>,
+
+    /// do not rate limit clients
+    #[clap(long = "no-rate-limit", env = "SERVER_NO_RATE_LIMIT",
+        num_args = 0..=1, default_missing_value = "true")]
+    pub no_rate_limit: Option<bool>,
+
+    /// rate limits per path prefix, only settable in the config file as
+    /// `[[rate_limit]]` tables [default: 100 requests at once to /api,
+    /// refilled at 20 per second, and 3 to /api/contact, refilled at 1 per
+    /// minute]
+    #[clap(skip)]
+    pub rate_limit: Option<Vec<RateLimitRule>>,
+
+    /// set who gets the messages of the contact form, like
+    /// `Site <owner@example.com>`
+    #[clap(long = "contact-to", env = "SERVER_CONTACT_TO")]
+    pub contact_to: Option<String>,
+
+    /// set the sender of contact form messages [default: --contact-to]
+    #[clap(long = "contact-from", env = "SERVER_CONTACT_FROM")]
+    pub contact_from: Option<String>,
+
+    /// send contact form messages through this `smtp://` or `smtps://` url
+    #[clap(long = "smtp-url", env = "SERVER_SMTP_URL", hide_env_values = true)]
+    pub smtp_url: Option<String>,
+
+    /// write contact form messages into this maildir instead of sending them
+    #[clap(long = "contact-maildir", env = "SERVER_CONTACT_MAILDIR")]
+    pub contact_maildir: Option<PathBuf>,
+
+    /// set the key signing the tokens of the contact form, which every
+    /// instance serving it must share [default: random per process]
+    #[clap(
+        long = "contact-secret",
+        env = "SERVER_CONTACT_SECRET",
+        hide_env_values = true
+    )]
+    pub contact_secret: Option<String>,
+
+    /// security headers, only settable in the config file as the
+    /// `[security_headers]` table
+    #[clap(skip)]
+    pub security_headers: Option<SecurityHeadersOpt>,
+
+    /// set a path prefix, like a deployment stage, that the vercel function
+    /// removes before routing, so `/prod/api/posts` is served as `/api/posts`
+    #[clap(long = "vercel-strip-prefix", env = "SERVER_VERCEL_STRIP_PREFIX")]
+    pub vercel_strip_prefix: Option<String>,
+
+    /// set the media types the vercel function answers as text, `type/*` and
+    /// `application/*+json` patterns included; everything else and every
+    /// compressed body goes out as binary [default: text/*, application/json,
+    /// application/*+json, application/javascript, application/xml,
+    /// application/*+xml, image/svg+xml]
+    #[clap(
+        long = "vercel-text-type",
+        env = "SERVER_VERCEL_TEXT_TYPES",
+        value_delimiter = ','
+    )]
+    pub vercel_text_types: Option<Vec<String>>,
+
+    /// stream responses of the vercel function as they are produced, except
+    /// those known to be at most this many bytes; the function needs
+    /// response streaming enabled [default: buffer every response]
+    #[clap(long = "vercel-stream-threshold", env = "SERVER_VERCEL_STREAM_THRESHOLD")]
+    pub vercel_stream_threshold: Option<usize>,
+
+    /// watch the static dir and reload connected browsers when it changes;
+    /// only a flag with the `dev` feature, the config file key is ignored
+    /// without it so the file works for every build
+    #[cfg_attr(
+        feature = "dev",
+        clap(long = "dev", env = "SERVER_DEV", num_args = 0..=1, default_missing_value = "true")
+    )]
+    #[cfg_attr(not(feature = "dev"), clap(skip))]
+    pub dev: Option<bool>,
+
+    #[clap(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// write .br and .gz siblings for every file in the static dir, then exit
+    Compress,
+    /// serve the way a vercel deployment would, routing by vercel.json to
+    /// static files and to the app run as the vercel function
+    EmulateVercel {
+        /// route by this vercel.json; the files it routes to are relative to
+        /// its directory
+        #[clap(long = "vercel-json", default_value = "vercel.json")]
+        vercel_json: PathBuf,
+    },
+}
+
+impl Opt {
+    /// Fill every setting missing from `self` with the one from `lower`.
+    fn or(self, lower: Opt) -> Opt {
+        Opt {
+            config: self.config.or(lower.config),
+            log_level: self.log_level.or(lower.log_level),
+            log_format: self.log_format.or(lower.log_format),
+            addr: self.addr.or(lower.addr),
+            port: self.port.or(lower.port),
+            static_dir: self.static_dir.or(lower.static_dir),
+            content_dir: self.content_dir.or(lower.content_dir),
+            base_url: self.base_url.or(lower.base_url),
+            robots_txt: self.robots_txt.or(lower.robots_txt),
+            hashed_max_age: self.hashed_max_age.or(lower.hashed_max_age),
+            no_cache_headers: self.no_cache_headers.or(lower.no_cache_headers),
+            tls_cert: self.tls_cert.or(lower.tls_cert),
+            tls_key: self.tls_key.or(lower.tls_key),
+            http_redirect_port: self.http_redirect_port.or(lower.http_redirect_port),
+            shutdown_timeout: self.shutdown_timeout.or(lower.shutdown_timeout),
+            metrics_path: self.metrics_path.or(lower.metrics_path),
+            metrics_token: self.metrics_token.or(lower.metrics_token),
+            no_metrics: self.no_metrics.or(lower.no_metrics),
+            admin_token: self.admin_token.or(lower.admin_token),
+            no_api_docs: self.no_api_docs.or(lower.no_api_docs),
+            trusted_proxies: self.trusted_proxies.or(lower.trusted_proxies),
+            no_rate_limit: self.no_rate_limit.or(lower.no_rate_limit),
+            rate_limit: self.rate_limit.or(lower.rate_limit),
+            security_headers: self.security_headers.or(lower.security_headers),
+            contact_to: self.contact_to.or(lower.contact_to),
+            contact_from: self.contact_from.or(lower.contact_from),
+            smtp_url: self.smtp_url.or(lower.smtp_url),
+            contact_maildir: self.contact_maildir.or(lower.contact_maildir),
+            contact_secret: self.contact_secret.or(lower.contact_secret),
+            vercel_strip_prefix: self.vercel_strip_prefix.or(lower.vercel_strip_prefix),
+            vercel_text_types: self.vercel_text_types.or(lower.vercel_text_types),
+            vercel_stream_threshold: self
+                .vercel_stream_threshold
+                .or(lower.vercel_stream_threshold),
+            dev: self.dev.or(lower.dev),
+            command: self.command.or(lower.command),
+        }
+    }
+}
+
+/// The validated configuration shared by the server and vercel binaries.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub addr: SocketAddr,
+    pub static_dir: PathBuf,
+    pub content_dir: PathBuf,
+    /// absolute site url without a trailing slash
+    pub base_url: String,
+    pub robots_txt: Option<PathBuf>,
+    pub hashed_max_age: u64,
+    pub cache_headers: bool,
+    pub tls: Option<TlsConfig>,
+    /// where to redirect plain HTTP to HTTPS, only set along with `tls`
+    pub http_redirect: Option<SocketAddr>,
+    pub shutdown_timeout: Duration,
+    /// `None` when metrics are disabled
+    pub metrics: Option<MetricsConfig>,
+    /// `None` when the admin endpoints are disabled
+    pub admin_token: Option<String>,
+    pub api_docs: bool,
+    /// empty when rate limiting is disabled
+    pub rate_limits: Vec<RateLimitRule>,
+    pub trusted_proxies: Vec<IpNet>,
+    pub security_headers: SecurityHeaders,
+    /// `None` when there is no contact form
+    pub contact: Option<ContactConfig>,
+    /// only used by the vercel binary and `emulate-vercel`
+    pub vercel_strip_prefix: Option<String>,
+    /// only used by the vercel binary and `emulate-vercel`, `None` for its
+    /// defaults
+    pub vercel_text_types: Option<Vec<String>>,
+    /// only used by the vercel binary and `emulate-vercel`, `None` to buffer
+    /// every response
+    pub vercel_stream_threshold: Option<usize>,
+    #[cfg(feature = "dev")]
+    pub dev: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        key: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "failed to read config file {}: {source}", path.display())
+            }
+            ConfigError::Parse { path, source } => {
+                write!(f, "invalid config file {}: {source}", path.display())
+            }
+            ConfigError::Invalid { key, value, reason } => {
+                write!(f, "invalid {key} {value:?}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl Config {
+    /// Merge `opt`, which clap has already filled from flags and the
+    /// environment, over the config file and the defaults.
+    pub fn load(opt: Opt) -> Result<Self, ConfigError> {
+        let file = match &opt.config {
+            Some(path) => read_file(path)?,
+            // the default file is optional
+            None if Path::new(DEFAULT_CONFIG).is_file() => read_file(Path::new(DEFAULT_CONFIG))?,
+            None => Opt::default(),
+        };
+        Self::validate(opt.or(file))
+    }
+
+    fn validate(opt: Opt) -> Result<Self, ConfigError> {
+        let ip = match opt.addr {
+            None => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            Some(addr) => {
+                addr.parse()
+                    .map_err(|err: std::net::AddrParseError| ConfigError::Invalid {
+                        key: "addr",
+                        value: addr.clone(),
+                        reason: err.to_string(),
+                    })?
+            }
+        };
+
+        let tls = match (opt.tls_cert, opt.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsConfig { cert, key }),
+            (None, None) => None,
+            (cert, key) => {
+                let (key, value) = match (cert, key) {
+                    (Some(cert), _) => ("tls_cert", cert),
+                    (_, key) => ("tls_key", key.unwrap_or_default()),
+                };
+                return Err(ConfigError::Invalid {
+                    key,
+                    value: value.display().to_string(),
+                    reason: "tls_cert and tls_key must be set together".to_owned(),
+                });
+            }
+        };
+        let port = opt.port.unwrap_or(8080);
+        if port == 0 {
+            return Err(ConfigError::Invalid {
+                key: "port",
+                value: port.to_string(),
+                reason: "must be a fixed port, links and redirects point at it".to_owned(),
+            });
+        }
+        let addr = SocketAddr::from((ip, port));
+        let base_url = opt
+            .base_url
+            .unwrap_or_else(|| default_base_url(addr, tls.is_some()));
+        validate_base_url(&base_url).map_err(|reason| ConfigError::Invalid {
+            key: "base_url",
+            value: base_url.clone(),
+            reason,
+        })?;
+        let http_redirect = match opt.http_redirect_port {
+            Some(port) if tls.is_none() => {
+                return Err(ConfigError::Invalid {
+                    key: "http_redirect_port",
+                    value: port.to_string(),
+                    reason: "redirecting to HTTPS needs tls_cert and tls_key".to_owned(),
+                })
+            }
+            Some(redirect_port) if redirect_port == 0 || redirect_port == port => {
+                return Err(ConfigError::Invalid {
+                    key: "http_redirect_port",
+                    value: redirect_port.to_string(),
+                    reason: "must be a fixed port other than port".to_owned(),
+                })
+            }
+            port => port.map(|port| SocketAddr::from((ip, port))),
+        };
+
+        let metrics_path = opt.metrics_path.unwrap_or_else(|| "/metrics".to_owned());
+        if let Err(reason) = validate_metrics_path(&metrics_path) {
+            return Err(ConfigError::Invalid {
+                key: "metrics_path",
+                value: metrics_path,
+                reason,
+            });
+        }
+        let vercel_strip_prefix = opt
+            .vercel_strip_prefix
+            .filter(|prefix| !prefix.is_empty());
+        if let Some(prefix) = &vercel_strip_prefix {
+            if !prefix.starts_with('/') {
+                return Err(ConfigError::Invalid {
+                    key: "vercel_strip_prefix",
+                    value: prefix.clone(),
+                    reason: "must start with /".to_owned(),
+                });
+            }
+        }
+
+        let vercel_text_types = opt.vercel_text_types;
+        for pattern in vercel_text_types.iter().flatten() {
+            if !pattern.contains('/') {
+                return Err(ConfigError::Invalid {
+                    key: "vercel_text_types",
+                    value: pattern.clone(),
+                    reason: "must be a media type like text/plain or text/*".to_owned(),
+                });
+            }
+        }
+
+        let metrics = (!opt.no_metrics.unwrap_or(false)).then(|| MetricsConfig {
+            path: metrics_path,
+            token: opt.metrics_token.filter(|token| !token.is_empty()),
+        });
+
+        let trusted_proxies = opt
+            .trusted_proxies
+            .unwrap_or_default()
+            .into_iter()
+            .map(|proxy| {
+                proxy.parse().map_err(|reason| ConfigError::Invalid {
+                    key: "trusted_proxies",
+                    value: proxy,
+                    reason,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let rate_limits = match opt.no_rate_limit {
+            Some(true) => Vec::new(),
+            _ => opt.rate_limit.unwrap_or_else(RateLimitRule::default_rules),
+        };
+        for rule in &rate_limits {
+            rule.validate().map_err(|reason| ConfigError::Invalid {
+                key: "rate_limit",
+                value: rule.prefix.clone(),
+                reason,
+            })?;
+        }
+
+        let security_headers = SecurityHeaders::new(opt.security_headers.unwrap_or_default())
+            .map_err(|(key, value)| ConfigError::Invalid {
+                key,
+                value,
+                reason: "not a valid header value".to_owned(),
+            })?;
+
+        let contact = validate_contact(
+            opt.contact_to,
+            opt.contact_from,
+            opt.smtp_url,
+            opt.contact_maildir,
+            opt.contact_secret,
+        )?;
+
+        #[cfg(not(feature = "dev"))]
+        if opt.dev == Some(true) {
+            // logging is not set up yet
+            eprintln!("ignoring dev = true, this server was built without the dev feature");
+        }
+
+        Ok(Config {
+            log_level: opt.log_level.unwrap_or_else(|| "debug".to_owned()),
+            log_format: opt.log_format.unwrap_or_default(),
+            addr,
+            static_dir: opt.static_dir.unwrap_or_else(|| "./dist".into()),
+            content_dir: opt.content_dir.unwrap_or_else(|| "./content".into()),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            robots_txt: opt.robots_txt,
+            hashed_max_age: opt.hashed_max_age.unwrap_or(31_536_000),
+            cache_headers: !opt.no_cache_headers.unwrap_or(false),
+            tls,
+            http_redirect,
+            metrics,
+            rate_limits,
+            trusted_proxies,
+            security_headers,
+            contact,
+            vercel_strip_prefix,
+            vercel_text_types,
+            vercel_stream_threshold: opt.vercel_stream_threshold,
+            admin_token: opt.admin_token.filter(|token| !token.is_empty()),
+            api_docs: !opt.no_api_docs.unwrap_or(false),
+            shutdown_timeout: Duration::from_secs(opt.shutdown_timeout.unwrap_or(30)),
+            #[cfg(feature = "dev")]
+            dev: opt.dev.unwrap_or(false),
+        })
+    }
+}
+
+fn read_file(path: &Path) -> Result<Opt, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    toml::from_str(&text).map_err(|source| ConfigError::Parse {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn validate_contact(
+    to: Option<String>,
+    from: Option<String>,
+    smtp_url: Option<String>,
+    maildir: Option<PathBuf>,
+    secret: Option<String>,
+) -> Result<Option<ContactConfig>, ConfigError> {
+    let delivery = match (smtp_url, maildir) {
+        (None, None) => return Ok(None),
+        (Some(url), None) => DeliveryConfig::Smtp(url),
+        (None, Some(dir)) => DeliveryConfig::Maildir(dir),
+        (Some(_), Some(dir)) => {
+            return Err(ConfigError::Invalid {
+                key: "contact_maildir",
+                value: dir.display().to_string(),
+                reason: "set either smtp_url or contact_maildir, not both".to_owned(),
+            })
+        }
+    };
+    let mailbox = |key, value: String| -> Result<Mailbox, ConfigError> {
+        value.parse().map_err(|err| ConfigError::Invalid {
+            key,
+            value,
+            reason: format!("{err}"),
+        })
+    };
+    let Some(to) = to else {
+        return Err(ConfigError::Invalid {
+            key: "contact_to",
+            value: String::new(),
+            reason: "needed to deliver contact form messages".to_owned(),
+        });
+    };
+    let to = mailbox("contact_to", to)?;
+    let from = match from {
+        Some(from) => mailbox("contact_from", from)?,
+        None => to.clone(),
+    };
+    Ok(Some(ContactConfig {
+        to,
+        from,
+        delivery,
+        secret: secret.filter(|secret| !secret.is_empty()),
+    }))
+}
+
+/// Paths the app serves itself outside `/api`, which the metrics route
+/// would collide with.
+const APP_PATHS: &[&str] = &[
+    "/",
+    "/feed.xml",
+    "/atom.xml",
+    "/feed.json",
+    "/sitemap.xml",
+    "/robots.txt",
+    "/__livereload",
+];
+
+fn validate_metrics_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err("must start with /".to_owned());
+    }
+    if path.contains(['{', '}']) {
+        return Err("must not contain route parameters".to_owned());
+    }
+    if path == "/api" || path.starts_with("/api/") {
+        return Err("must not be under /api".to_owned());
+    }
+    if APP_PATHS.contains(&path) {
+        return Err("is already served by the app".to_owned());
+    }
+    Ok(())
+}
+
+/// The url the server is reachable at on this machine, for a local setup
+/// without a public url.
+fn default_base_url(addr: SocketAddr, tls: bool) -> String {
+    let scheme = if tls { "https" } else { "http" };
+    let ip = addr.ip();
+    if ip.is_loopback() || ip.is_unspecified() {
+        format!("{scheme}://localhost:{}", addr.port())
+    } else {
+        format!("{scheme}://{addr}")
+    }
+}
+
+fn validate_base_url(base_url: &str) -> Result<(), String> {
+    let uri: Uri = base_url.parse().map_err(|err| format!("{err}"))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err("must start with http:// or https://".to_owned());
+    }
+    if uri.host().is_none() {
+        return Err("must include a host".to_owned());
+    }
+    if uri.query().is_some() {
+        return Err("must not have a query".to_owned());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config file with `toml` in a temporary dir, kept alive by the
+    /// returned guard.
+    fn config_file(toml: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        std::fs::write(&path, toml).unwrap();
+        (dir, path)
+    }
+
+    fn load(toml: &str, opt: Opt) -> Result<Config, ConfigError> {
+        let (_dir, path) = config_file(toml);
+        Config::load(Opt {
+            config: Some(path),
+            ..opt
+        })
+    }
+
+    fn invalid_key(result: Result<Config, ConfigError>) -> &'static str {
+        match result {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            other => panic!("not invalid: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn layers() {
+        let (_dir, path) = config_file(
+            "addr = \"127.0.0.1\"\nport = 1000\nlog_level = \"warn\"\nhashed_max_age = 60\n",
+        );
+        // the only test touching these, clap reads them while parsing
+        std::env::set_var("SERVER_PORT", "2000");
+        std::env::set_var("SERVER_ADDR", "127.0.0.2");
+        let opt = Opt::try_parse_from([
+            "server".as_ref(),
+            "--config".as_ref(),
+            path.as_os_str(),
+            "--port".as_ref(),
+            "3000".as_ref(),
+        ]);
+        std::env::remove_var("SERVER_PORT");
+        std::env::remove_var("SERVER_ADDR");
+        let config = Config::load(opt.unwrap()).unwrap();
+
+        // the flag over the environment and the file
+        assert_eq!(config.addr.port(), 3000);
+        // the environment over the file
+        assert_eq!(config.addr.ip().to_string(), "127.0.0.2");
+        // the file over the defaults
+        assert_eq!(config.log_level, "warn");
+        assert_eq!(config.hashed_max_age, 60);
+        // the defaults
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.static_dir, Path::new("./dist"));
+        assert_eq!(config.base_url, "http://localhost:3000");
+    }
+
+    #[test]
+    fn defaults() {
+        let config = load("", Opt::default()).unwrap();
+        assert_eq!(config.addr, "[::1]:8080".parse().unwrap());
+        assert_eq!(config.base_url, "http://localhost:8080");
+        assert_eq!(config.log_level, "debug");
+        assert!(config.cache_headers);
+        assert!(config.tls.is_none());
+        assert!(config.contact.is_none());
+    }
+
+    #[test]
+    fn base_url_follows_addr_and_port() {
+        let base_url = |toml| load(toml, Opt::default()).unwrap().base_url;
+        assert_eq!(base_url("port = 3000"), "http://localhost:3000");
+        assert_eq!(base_url("addr = \"0.0.0.0\""), "http://localhost:8080");
+        assert_eq!(base_url("addr = \"::\"\nport = 80"), "http://localhost:80");
+        assert_eq!(base_url("addr = \"192.0.2.1\""), "http://192.0.2.1:8080");
+        assert_eq!(
+            base_url("addr = \"2001:db8::1\""),
+            "http://[2001:db8::1]:8080"
+        );
+        assert_eq!(
+            base_url("base_url = \"https://example.com/blog/\"\nport = 3000"),
+            "https://example.com/blog"
+        );
+        assert_eq!(
+            default_base_url("[::1]:8443".parse().unwrap(), true),
+            "https://localhost:8443"
+        );
+    }
+
+    #[test]
+    fn invalid_addr_and_port() {
+        for addr in ["localhost", "127.0.0.1:8080", "::1::", ""] {
+            let opt = Opt {
+                addr: Some(addr.to_owned()),
+                ..Opt::default()
+            };
+            assert_eq!(invalid_key(load("", opt)), "addr", "{addr}");
+        }
+        assert_eq!(invalid_key(load("port = 0", Opt::default())), "port");
+        // out of range is already a parse error of the file
+        assert!(matches!(
+            load("port = 70000", Opt::default()),
+            Err(ConfigError::Parse { .. })
+        ));
+        assert!(Opt::try_parse_from(["server", "--port", "70000"]).is_err());
+    }
+
+    #[test]
+    fn dev_key_is_accepted_by_every_build() {
+        let config = load("dev = true", Opt::default());
+        assert!(config.is_ok(), "{config:?}");
+    }
+}
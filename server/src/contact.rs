@@ -0,0 +1,739 @@
+//! `POST /api/contact`, where the contact form of the frontend sends
+//! messages, as JSON or as an urlencoded form.
+//!
+//! Two cheap checks keep most spam out. The form has a `website` field that
+//! is hidden from people, so anything filling it in is a bot. And it sends
+//! the token of `GET /api/contact-token`, fetched when it was shown, which
+//! holds the time it was issued, signed by the server: forms sent within
+//! seconds of it were not filled in by hand. Spam gets the same answer as a
+//! real message, so bots learn nothing, but is only logged. A missing,
+//! forged or day old token is a field error instead, since the page of a
+//! person can simply be reloaded. Flooding is left to the rate limiter.
+//!
+//! Messages are sent on through a [`Delivery`]: SMTP in production, or a
+//! maildir for development, which any mail client can open.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::extract::{DefaultBodyLimit, FromRequest, Request, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Form, Json};
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use lettre::message::Mailbox;
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use ring::hmac;
+use shared::api::{ContactInvalid, ContactRequest, ContactSent, ContactToken, Problem};
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use crate::error::{self, ApiError};
+
+/// Forms sent faster than this after being shown were not filled in by hand.
+const MIN_FILL_TIME: Duration = Duration::from_secs(3);
+/// Tokens older than this are expired, so a token cannot be reused forever.
+const MAX_TOKEN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// What people are told when their form has no valid token.
+const EXPIRED: &str = "The form has expired, please reload the page and send it again.";
+const MAX_NAME_LEN: usize = 100;
+const MAX_MESSAGE_LEN: usize = 5000;
+
+#[derive(Debug, Clone)]
+pub struct ContactConfig {
+    /// who gets the messages
+    pub to: Mailbox,
+    /// the sender of the messages, visitors are only the reply-to
+    pub from: Mailbox,
+    pub delivery: DeliveryConfig,
+    /// the key of the form tokens, `None` for a random one per process
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DeliveryConfig {
+    /// an `smtp://` or `smtps://` url, with credentials if needed
+    Smtp(String),
+    Maildir(PathBuf),
+}
+
+/// A message that passed validation.
+#[derive(Debug, Clone)]
+pub struct ContactMessage {
+    pub name: String,
+    pub email: Address,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum DeliveryError {
+    Io(io::Error),
+    Email(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryError::Io(err) => write!(f, "failed to write message: {err}"),
+            DeliveryError::Email(err) => write!(f, "failed to build message: {err}"),
+            DeliveryError::Smtp(err) => write!(f, "failed to send message: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DeliveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeliveryError::Io(err) => Some(err),
+            DeliveryError::Email(err) => Some(err),
+            DeliveryError::Smtp(err) => Some(err),
+        }
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Where contact messages end up.
+pub trait Delivery: Send + Sync {
+    fn deliver<'a>(
+        &'a self,
+        message: &'a ContactMessage,
+    ) -> BoxFuture<'a, Result<(), DeliveryError>>;
+}
+
+/// The email sent for `message`, with the visitor as reply-to.
+fn email(from: &Mailbox, to: &Mailbox, message: &ContactMessage) -> Result<Message, DeliveryError> {
+    let visitor = Mailbox::new(Some(message.name.clone()), message.email.clone());
+    Message::builder()
+        .from(from.clone())
+        .reply_to(visitor)
+        .to(to.clone())
+        .subject(format!("Contact form: {}", message.name))
+        .body(message.message.clone())
+        .map_err(DeliveryError::Email)
+}
+
+pub struct Smtp {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+impl Smtp {
+    pub fn new(url: &str, from: Mailbox, to: Mailbox) -> Result<Self, DeliveryError> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(DeliveryError::Smtp)?
+            .build();
+        Ok(Self {
+            transport,
+            from,
+            to,
+        })
+    }
+}
+
+impl Delivery for Smtp {
+    fn deliver<'a>(
+        &'a self,
+        message: &'a ContactMessage,
+    ) -> BoxFuture<'a, Result<(), DeliveryError>> {
+        Box::pin(async move {
+            let email = email(&self.from, &self.to, message)?;
+            self.transport
+                .send(email)
+                .await
+                .map_err(DeliveryError::Smtp)?;
+            Ok(())
+        })
+    }
+}
+
+/// Writes every message as a file into the `new` directory of a maildir.
+pub struct Maildir {
+    dir: PathBuf,
+    from: Mailbox,
+    to: Mailbox,
+    delivered: AtomicU64,
+}
+
+impl Maildir {
+    pub fn new(dir: PathBuf, from: Mailbox, to: Mailbox) -> Self {
+        Self {
+            dir,
+            from,
+            to,
+            delivered: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Delivery for Maildir {
+    fn deliver<'a>(
+        &'a self,
+        message: &'a ContactMessage,
+    ) -> BoxFuture<'a, Result<(), DeliveryError>> {
+        Box::pin(async move {
+            let email = email(&self.from, &self.to, message)?.formatted();
+            for sub in ["tmp", "new", "cur"] {
+                tokio::fs::create_dir_all(self.dir.join(sub))
+                    .await
+                    .map_err(DeliveryError::Io)?;
+            }
+
+            // unique per the maildir spec: time, process and a counter
+            let now = chrono::Utc::now();
+            let name = format!(
+                "{}.M{}P{}Q{}.server",
+                now.timestamp(),
+                now.timestamp_subsec_micros(),
+                std::process::id(),
+                self.delivered.fetch_add(1, Ordering::Relaxed),
+            );
+            // written to `tmp` first, so readers never see half a message
+            let tmp = self.dir.join("tmp").join(&name);
+            tokio::fs::write(&tmp, email)
+                .await
+                .map_err(DeliveryError::Io)?;
+            tokio::fs::rename(&tmp, self.dir.join("new").join(&name))
+                .await
+                .map_err(DeliveryError::Io)?;
+            Ok(())
+        })
+    }
+}
+
+/// The delivery for `config`.
+pub fn delivery(config: &ContactConfig) -> Result<Arc<dyn Delivery>, DeliveryError> {
+    let (from, to) = (config.from.clone(), config.to.clone());
+    Ok(match &config.delivery {
+        DeliveryConfig::Smtp(url) => Arc::new(Smtp::new(url, from, to)?),
+        DeliveryConfig::Maildir(dir) => Arc::new(Maildir::new(dir.clone(), from, to)),
+    })
+}
+
+/// Why a form token was not taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenError {
+    Missing,
+    Malformed,
+    Forged,
+    /// issued after now, by a server with a clock ahead
+    FromTheFuture,
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Missing => write!(f, "no form token"),
+            TokenError::Malformed => write!(f, "malformed form token"),
+            TokenError::Forged => write!(f, "form token with a wrong signature"),
+            TokenError::FromTheFuture => write!(f, "form token issued in the future"),
+            TokenError::Expired => write!(f, "expired form token"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Issues and checks the tokens that say when a form was shown: the time
+/// in unix milliseconds and an HMAC of it, like `1700000000000.<mac>`.
+#[derive(Clone)]
+pub struct FormTokens {
+    key: hmac::Key,
+}
+
+impl FormTokens {
+    /// Tokens signed with `secret`, or with a random key if there is none,
+    /// which only this process can check.
+    pub fn new(secret: Option<&str>) -> Self {
+        let key = match secret {
+            Some(secret) => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            None => hmac::Key::generate(hmac::HMAC_SHA256, &ring::rand::SystemRandom::new())
+                .expect("no randomness for the form token key"),
+        };
+        Self { key }
+    }
+
+    /// A token issued at `now`, in unix milliseconds.
+    pub fn issue(&self, now: u64) -> String {
+        let issued = now.to_string();
+        let tag = hmac::sign(&self.key, issued.as_bytes());
+        format!("{issued}.{}", BASE64_URL_SAFE_NO_PAD.encode(tag))
+    }
+
+    /// How long ago `token` was issued, at `now` in unix milliseconds.
+    pub fn age(&self, token: &str, now: u64) -> Result<Duration, TokenError> {
+        if token.is_empty() {
+            return Err(TokenError::Missing);
+        }
+        let (issued, tag) = token.split_once('.').ok_or(TokenError::Malformed)?;
+        let tag = BASE64_URL_SAFE_NO_PAD
+            .decode(tag)
+            .map_err(|_| TokenError::Malformed)?;
+        hmac::verify(&self.key, issued.as_bytes(), &tag).map_err(|_| TokenError::Forged)?;
+        let issued: u64 = issued.parse().map_err(|_| TokenError::Malformed)?;
+        let age = Duration::from_millis(now.checked_sub(issued).ok_or(TokenError::FromTheFuture)?);
+        if age > MAX_TOKEN_AGE {
+            return Err(TokenError::Expired);
+        }
+        Ok(age)
+    }
+}
+
+impl fmt::Debug for FormTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormTokens").finish_non_exhaustive()
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+#[derive(Clone)]
+struct ContactState {
+    delivery: Arc<dyn Delivery>,
+    tokens: FormTokens,
+}
+
+pub fn router(delivery: Arc<dyn Delivery>, tokens: FormTokens) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(contact))
+        .routes(routes!(token))
+        .layer(DefaultBodyLimit::max(16 * 1024))
+        .with_state(ContactState { delivery, tokens })
+}
+
+/// The field errors, keyed by field name, if there are any.
+fn validate(form: ContactRequest) -> Result<ContactMessage, BTreeMap<String, String>> {
+    let mut errors = BTreeMap::new();
+    let mut error = |field: &str, message: String| errors.insert(field.to_owned(), message);
+    let name = form.name.trim().to_owned();
+    if name.is_empty() {
+        error("name", "Please tell us your name.".to_owned());
+    } else if name.chars().count() > MAX_NAME_LEN {
+        error("name", format!("At most {MAX_NAME_LEN} characters please."));
+    }
+    let email = form.email.trim().parse::<Address>();
+    if email.is_err() {
+        error(
+            "email",
+            "This does not look like an email address.".to_owned(),
+        );
+    }
+    let message = form.message.trim().to_owned();
+    if message.is_empty() {
+        error("message", "The message is empty.".to_owned());
+    } else if message.chars().count() > MAX_MESSAGE_LEN {
+        error(
+            "message",
+            format!("At most {MAX_MESSAGE_LEN} characters please."),
+        );
+    }
+
+    match email {
+        Ok(email) if errors.is_empty() => Ok(ContactMessage {
+            name,
+            email,
+            message,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Whether `form`, shown `age` ago, was sent by a bot.
+fn is_spam(form: &ContactRequest, age: Duration) -> bool {
+    !form.website.is_empty() || age < MIN_FILL_TIME
+}
+
+/// A token for a contact form about to be shown. Forms sent with it within
+/// a few seconds, or a day after, are not taken. Outside the `/api/contact`
+/// prefix, so showing a form does not count against sending one.
+#[utoipa::path(
+    get,
+    path = "/api/contact-token",
+    tag = "contact",
+    responses((status = OK, body = ContactToken))
+)]
+async fn token(State(state): State<ContactState>) -> Response {
+    let token = ContactToken {
+        token: state.tokens.issue(unix_millis()),
+    };
+    ([(header::CACHE_CONTROL, "no-store")], Json(token)).into_response()
+}
+
+/// Sends a message through the contact form. Messages that look like spam
+/// get the same answer as real ones.
+#[utoipa::path(
+    post,
+    path = "/api/contact",
+    tag = "contact",
+    request_body(content(
+        (ContactRequest = "application/json"),
+        (ContactRequest = "application/x-www-form-urlencoded"),
+    )),
+    responses(
+        (status = OK, body = ContactSent),
+        (status = UNPROCESSABLE_ENTITY, description = "Some fields are invalid", body = ContactInvalid, content_type = "application/problem+json"),
+        (status = TOO_MANY_REQUESTS, description = "Too many messages from this client", body = Problem, content_type = "application/problem+json"),
+        (status = SERVICE_UNAVAILABLE, description = "The message could not be delivered", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn contact(State(state): State<ContactState>, req: Request) -> Response {
+    let is_json = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let form = if is_json {
+        Json::<ContactRequest>::from_request(req, &())
+            .await
+            .map(|Json(form)| form)
+            .map_err(IntoResponse::into_response)
+    } else {
+        Form::<ContactRequest>::from_request(req, &())
+            .await
+            .map(|Form(form)| form)
+            .map_err(IntoResponse::into_response)
+    };
+    let form = match form {
+        Ok(form) => form,
+        Err(res) => return res,
+    };
+
+    let age = state.tokens.age(&form.form_token, unix_millis());
+    let spam = age.is_ok_and(|age| is_spam(&form, age));
+    let message = match (validate(form), age) {
+        (Ok(message), Ok(_)) => message,
+        (message, age) => {
+            let mut errors = message.err().unwrap_or_default();
+            if let Err(err) = age {
+                log::info!("refused contact message: {err}");
+                errors.insert("form".to_owned(), EXPIRED.to_owned());
+            }
+            let status = StatusCode::UNPROCESSABLE_ENTITY;
+            let body = ContactInvalid {
+                problem: ApiError::new(status)
+                    .detail("Some fields are invalid.")
+                    .problem(),
+                errors,
+            };
+            return error::problem_response(status, body);
+        }
+    };
+    if spam {
+        log::info!("dropped contact message from {} as spam", message.email);
+        return sent();
+    }
+
+    match state.delivery.deliver(&message).await {
+        Ok(()) => {
+            log::info!("delivered contact message from {}", message.email);
+            sent()
+        }
+        Err(err) => {
+            log::error!("{err}");
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE)
+                .detail("The message could not be sent, please try again later.")
+                .into_response()
+        }
+    }
+}
+
+fn sent() -> Response {
+    Json(ContactSent { sent: true }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn form(name: &str, email: &str, message: &str) -> ContactRequest {
+        ContactRequest {
+            name: name.to_owned(),
+            email: email.to_owned(),
+            message: message.to_owned(),
+            ..ContactRequest::default()
+        }
+    }
+
+    fn errors(form: ContactRequest) -> Vec<String> {
+        validate(form).unwrap_err().into_keys().collect()
+    }
+
+    #[test]
+    fn valid_form() {
+        let message = validate(form(" Ada ", " ada@example.com ", " Hi!\n")).unwrap();
+        assert_eq!(message.name, "Ada");
+        assert_eq!(message.email.to_string(), "ada@example.com");
+        assert_eq!(message.message, "Hi!");
+    }
+
+    #[test]
+    fn invalid_forms() {
+        assert_eq!(errors(form(" ", "", "\n")), ["email", "message", "name"]);
+        assert_eq!(errors(form("Ada", "ada", "Hi")), ["email"]);
+        assert_eq!(errors(form("Ada", "ada@", "Hi")), ["email"]);
+
+        let long_name = "a".repeat(MAX_NAME_LEN + 1);
+        assert_eq!(errors(form(&long_name, "ada@example.com", "Hi")), ["name"]);
+        // characters, not bytes
+        let name = "é".repeat(MAX_NAME_LEN);
+        assert!(validate(form(&name, "ada@example.com", "Hi")).is_ok());
+
+        let long_message = "a".repeat(MAX_MESSAGE_LEN + 1);
+        assert_eq!(
+            errors(form("Ada", "ada@example.com", &long_message)),
+            ["message"]
+        );
+    }
+
+    #[test]
+    fn spam() {
+        let honest = form("Ada", "ada@example.com", "Hi");
+        assert!(!is_spam(&honest, Duration::from_secs(30)));
+        assert!(!is_spam(&honest, MIN_FILL_TIME));
+        assert!(is_spam(&honest, Duration::from_millis(200)));
+
+        let honeypot = ContactRequest {
+            website: "https://spam.example".to_owned(),
+            ..honest
+        };
+        assert!(is_spam(&honeypot, Duration::from_secs(30)));
+    }
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    #[test]
+    fn token_age() {
+        let tokens = FormTokens::new(Some("secret"));
+        let token = tokens.issue(NOW);
+        assert_eq!(tokens.age(&token, NOW), Ok(Duration::ZERO));
+        assert_eq!(
+            tokens.age(&token, NOW + 4_500),
+            Ok(Duration::from_millis(4_500))
+        );
+        assert_eq!(tokens.age(&token, NOW - 1), Err(TokenError::FromTheFuture));
+        let day = MAX_TOKEN_AGE.as_millis() as u64;
+        assert!(tokens.age(&token, NOW + day).is_ok());
+        assert_eq!(tokens.age(&token, NOW + day + 1), Err(TokenError::Expired));
+
+        // the same secret is the same key, as across instances
+        assert!(FormTokens::new(Some("secret")).age(&token, NOW).is_ok());
+    }
+
+    #[test]
+    fn forged_tokens() {
+        let tokens = FormTokens::new(None);
+        let token = tokens.issue(NOW);
+        let (_, tag) = token.split_once('.').unwrap();
+
+        // an earlier time with the same signature
+        let backdated = format!("{}.{tag}", NOW - 60_000);
+        assert_eq!(tokens.age(&backdated, NOW), Err(TokenError::Forged));
+        // signed by another key
+        let other = FormTokens::new(None).issue(NOW);
+        assert_eq!(tokens.age(&other, NOW), Err(TokenError::Forged));
+        let other = FormTokens::new(Some("secret")).issue(NOW);
+        assert_eq!(tokens.age(&other, NOW), Err(TokenError::Forged));
+
+        assert_eq!(tokens.age("", NOW), Err(TokenError::Missing));
+        assert_eq!(
+            tokens.age(&NOW.to_string(), NOW),
+            Err(TokenError::Malformed)
+        );
+        assert_eq!(
+            tokens.age(&format!("{NOW}.!"), NOW),
+            Err(TokenError::Malformed)
+        );
+    }
+
+    /// A contact app delivering into a maildir in `dir`, and its tokens.
+    fn app(dir: &std::path::Path) -> (Router, FormTokens) {
+        let maildir = Maildir::new(
+            dir.to_owned(),
+            "Site <site@example.com>".parse().unwrap(),
+            "Owner <owner@example.com>".parse().unwrap(),
+        );
+        let tokens = FormTokens::new(None);
+        let (app, _) = router(Arc::new(maildir), tokens.clone()).split_for_parts();
+        (app, tokens)
+    }
+
+    async fn post(
+        app: &Router,
+        content_type: &str,
+        body: String,
+    ) -> (StatusCode, serde_json::Value) {
+        let req = Request::post("/api/contact")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// The messages in the maildir, oldest first.
+    fn delivered(dir: &std::path::Path) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(dir.join("new")) else {
+            return Vec::new();
+        };
+        let mut names: Vec<_> = entries.map(|entry| entry.unwrap().path()).collect();
+        names.sort();
+        names
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    fn shown_seconds_ago(tokens: &FormTokens, seconds: u64) -> String {
+        tokens.issue(unix_millis() - seconds * 1000)
+    }
+
+    #[tokio::test]
+    async fn json_message_is_delivered() {
+        let dir = tempfile::tempdir().unwrap();
+        let (app, tokens) = app(dir.path());
+        let body = serde_json::to_string(&ContactRequest {
+            form_token: shown_seconds_ago(&tokens, 10),
+            ..form("Ada Lovelace", "ada@example.com", "About the engine")
+        })
+        .unwrap();
+        let (status, body) = post(&app, "application/json", body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({"sent": true}));
+
+        let messages = delivered(dir.path());
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert!(
+            message.contains("From: Site <site@example.com>"),
+            "{message}"
+        );
+        assert!(
+            message.contains("To: Owner <owner@example.com>"),
+            "{message}"
+        );
+        assert!(
+            message.contains("Reply-To: \"Ada Lovelace\" <ada@example.com>"),
+            "{message}"
+        );
+        assert!(
+            message.contains("Subject: Contact form: Ada Lovelace"),
+            "{message}"
+        );
+        assert!(message.contains("About the engine"), "{message}");
+        assert!(std::fs::read_dir(dir.path().join("tmp"))
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn form_message_is_delivered() {
+        let dir = tempfile::tempdir().unwrap();
+        let (app, tokens) = app(dir.path());
+        let token = shown_seconds_ago(&tokens, 10);
+        let body = format!(
+            "name=Ada&email=ada%40example.com&message=Hi+there%21&website=&form_token={token}"
+        );
+        let (status, _) = post(&app, "application/x-www-form-urlencoded", body).await;
+        assert_eq!(status, StatusCode::OK);
+        let messages = delivered(dir.path());
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Hi there!"), "{}", messages[0]);
+    }
+
+    #[tokio::test]
+    async fn spam_is_answered_but_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (app, tokens) = app(dir.path());
+        let too_fast = ContactRequest {
+            form_token: shown_seconds_ago(&tokens, 0),
+            ..form("Ada", "ada@example.com", "Hi")
+        };
+        let honeypot = ContactRequest {
+            form_token: shown_seconds_ago(&tokens, 10),
+            website: "https://spam.example".to_owned(),
+            ..form("Ada", "ada@example.com", "Hi")
+        };
+        for spam in [too_fast, honeypot] {
+            let body = serde_json::to_string(&spam).unwrap();
+            let (status, body) = post(&app, "application/json", body).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, serde_json::json!({"sent": true}));
+        }
+        assert!(delivered(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_fields_and_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let (app, tokens) = app(dir.path());
+
+        let body = serde_json::to_string(&ContactRequest {
+            form_token: shown_seconds_ago(&tokens, 10),
+            ..form("Ada", "not an address", "Hi")
+        })
+        .unwrap();
+        let (status, body) = post(&app, "application/json", body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let errors = body["errors"].as_object().unwrap();
+        assert_eq!(errors.keys().collect::<Vec<_>>(), ["email"]);
+
+        // no token at all, as from a bot posting directly
+        let body = "name=Ada&email=ada%40example.com&message=Hi".to_owned();
+        let (status, body) = post(&app, "application/x-www-form-urlencoded", body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errors"]["form"], EXPIRED);
+
+        let body = serde_json::to_string(&ContactRequest {
+            form_token: FormTokens::new(None).issue(unix_millis() - 10_000),
+            ..form("", "ada@example.com", "Hi")
+        })
+        .unwrap();
+        let (status, body) = post(&app, "application/json", body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let errors = body["errors"].as_object().unwrap();
+        assert_eq!(errors.keys().collect::<Vec<_>>(), ["form", "name"]);
+
+        assert!(delivered(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn token_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let (app, tokens) = app(dir.path());
+        let req = Request::get("/api/contact-token")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let token: ContactToken = serde_json::from_slice(&body).unwrap();
+        let age = tokens.age(&token.token, unix_millis()).unwrap();
+        assert!(age < MIN_FILL_TIME);
+    }
+}
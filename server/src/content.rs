@@ -0,0 +1,477 @@
+//! Markdown posts loaded from the content directory.
+//!
+//! Every `.md` file in the directory is one post. A post starts with a
+//! frontmatter block, either TOML fenced by `+++` or YAML fenced by `---`:
+//!
+//! ```text
+//! +++
+//! title = "Hello"
+//! date = 2025-05-01
+//! tags = ["meta"]
+//! draft = false
+//! +++
+//! Body in *Markdown*.
+//! ```
+//!
+//! The slug is the file stem unless the frontmatter sets `slug` explicitly.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::NaiveDate;
+use pulldown_cmark::{html, Options, Parser};
+use serde::Deserialize;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use crate::error::ApiError;
+
+#[derive(Deserialize)]
+struct Frontmatter {
+    title: String,
+    date: NaiveDate,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    draft: bool,
+    slug: Option<String>,
+}
+
+pub use shared::api::{Post, PostMeta};
+
+#[derive(Debug)]
+pub enum ContentError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    MissingFrontmatter {
+        path: PathBuf,
+    },
+    Frontmatter {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidSlug {
+        path: PathBuf,
+        slug: String,
+    },
+    SlugCollision {
+        slug: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentError::Io { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            ContentError::MissingFrontmatter { path } => {
+                write!(f, "{} has no +++ or --- frontmatter block", path.display())
+            }
+            ContentError::Frontmatter { path, message } => {
+                write!(f, "invalid frontmatter in {}: {message}", path.display())
+            }
+            ContentError::InvalidSlug { path, slug } => write!(
+                f,
+                "invalid slug {slug:?} in {}: use lowercase letters, digits, '-' and '_'",
+                path.display()
+            ),
+            ContentError::SlugCollision {
+                slug,
+                first,
+                second,
+            } => write!(
+                f,
+                "slug {slug:?} is used by both {} and {}",
+                first.display(),
+                second.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ContentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ContentError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// All published posts, newest first.
+#[derive(Default, Debug)]
+pub struct PostStore {
+    posts: Vec<Post>,
+    by_slug: HashMap<String, usize>,
+}
+
+impl PostStore {
+    /// Load every post in `dir`. A missing directory is an empty store, so a
+    /// site without written content still starts.
+    pub fn load(dir: &Path) -> Result<Self, ContentError> {
+        if !dir.is_dir() {
+            log::warn!("content dir {} not found, serving no posts", dir.display());
+            return Ok(Self::default());
+        }
+
+        let io_err = |source| ContentError::Io {
+            path: dir.to_owned(),
+            source,
+        };
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "md") {
+                paths.push(path);
+            }
+        }
+        // read_dir order is platform dependent; keep collision errors stable
+        paths.sort();
+
+        let mut posts = Vec::new();
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
+        for path in paths {
+            let source = std::fs::read_to_string(&path).map_err(|source| ContentError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            let (post, draft) = parse_post(&path, &source)?;
+            if let Some(first) = sources.insert(post.meta.slug.clone(), path.clone()) {
+                return Err(ContentError::SlugCollision {
+                    slug: post.meta.slug,
+                    first,
+                    second: path,
+                });
+            }
+            if !draft {
+                posts.push(post);
+            }
+        }
+
+        Ok(Self::from_posts(posts))
+    }
+
+    fn from_posts(mut posts: Vec<Post>) -> Self {
+        posts.sort_by(|a, b| {
+            b.meta
+                .date
+                .cmp(&a.meta.date)
+                .then_with(|| a.meta.slug.cmp(&b.meta.slug))
+        });
+        let by_slug = posts
+            .iter()
+            .enumerate()
+            .map(|(i, post)| (post.meta.slug.clone(), i))
+            .collect();
+        Self { posts, by_slug }
+    }
+
+    pub fn posts(&self) -> &[Post] {
+        &self.posts
+    }
+
+    pub fn get(&self, slug: &str) -> Option<&Post> {
+        self.by_slug.get(slug).map(|&i| &self.posts[i])
+    }
+}
+
+/// Parse one post file. The returned flag is the frontmatter `draft` value.
+fn parse_post(path: &Path, source: &str) -> Result<(Post, bool), ContentError> {
+    let (frontmatter, body) =
+        split_frontmatter(source).ok_or_else(|| ContentError::MissingFrontmatter {
+            path: path.to_owned(),
+        })?;
+    let frontmatter_err = |message: String| ContentError::Frontmatter {
+        path: path.to_owned(),
+        message,
+    };
+    let frontmatter: Frontmatter = match frontmatter {
+        RawFrontmatter::Toml(raw) => {
+            let mut table: toml::Table =
+                toml::from_str(raw).map_err(|e| frontmatter_err(e.to_string()))?;
+            // native TOML dates are not strings, hand chrono their text form
+            for (_, value) in table.iter_mut() {
+                if let toml::Value::Datetime(datetime) = value {
+                    *value = toml::Value::String(datetime.to_string());
+                }
+            }
+            table
+                .try_into()
+                .map_err(|e: toml::de::Error| frontmatter_err(e.to_string()))?
+        }
+        RawFrontmatter::Yaml(raw) => {
+            serde_yaml::from_str(raw).map_err(|e| frontmatter_err(e.to_string()))?
+        }
+    };
+
+    let slug = match frontmatter.slug {
+        Some(slug) => slug,
+        None => path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    if !is_valid_slug(&slug) {
+        return Err(ContentError::InvalidSlug {
+            path: path.to_owned(),
+            slug,
+        });
+    }
+
+    let post = Post {
+        meta: PostMeta {
+            slug,
+            title: frontmatter.title,
+            date: frontmatter.date,
+            tags: frontmatter.tags,
+        },
+        html: render_markdown(body),
+    };
+    Ok((post, frontmatter.draft))
+}
+
+enum RawFrontmatter<'a> {
+    Toml(&'a str),
+    Yaml(&'a str),
+}
+
+/// Split a post into its frontmatter and Markdown body.
+fn split_frontmatter(source: &str) -> Option<(RawFrontmatter<'_>, &str)> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let mut lines = source.split_inclusive('\n');
+    let fence = lines.next()?.trim_end();
+    if fence != "+++" && fence != "---" {
+        return None;
+    }
+
+    let start = source.find('\n')? + 1;
+    let mut offset = start;
+    for line in lines {
+        if line.trim_end() == fence {
+            let raw = &source[start..offset];
+            let body = &source[offset + line.len()..];
+            let raw = if fence == "+++" {
+                RawFrontmatter::Toml(raw)
+            } else {
+                RawFrontmatter::Yaml(raw)
+            };
+            return Some((raw, body));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+fn render_markdown(body: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_HEADING_ATTRIBUTES;
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new_ext(body, options));
+    out
+}
+
+pub fn router(store: Arc<PostStore>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(list_posts))
+        .routes(routes!(get_post))
+        .with_state(store)
+}
+
+/// All posts without their bodies, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/posts",
+    tag = "posts",
+    responses((status = OK, body = [PostMeta]))
+)]
+async fn list_posts(State(store): State<Arc<PostStore>>) -> impl IntoResponse {
+    let metas: Vec<&PostMeta> = store.posts().iter().map(|post| &post.meta).collect();
+    Json(metas).into_response()
+}
+
+/// One post with its body rendered to HTML.
+#[utoipa::path(
+    get,
+    path = "/api/posts/{slug}",
+    tag = "posts",
+    params(("slug" = String, Path, description = "The slug of the post")),
+    responses(
+        (status = OK, body = Post),
+        (status = NOT_FOUND, description = "There is no such post", body = shared::api::Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn get_post(
+    State(store): State<Arc<PostStore>>,
+    UrlPath(slug): UrlPath<String>,
+) -> impl IntoResponse {
+    match store.get(&slug) {
+        Some(post) => Json(post).into_response(),
+        None => ApiError::new(StatusCode::NOT_FOUND)
+            .detail(format!("There is no post named {slug}."))
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A content dir holding `files`, as (name, contents).
+    fn content_dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn load(files: &[(&str, &str)]) -> Result<PostStore, ContentError> {
+        PostStore::load(content_dir(files).path())
+    }
+
+    #[test]
+    fn missing_fence() {
+        let err = load(&[("a.md", "title = \"A\"\n\nBody\n")]).unwrap_err();
+        assert!(
+            matches!(err, ContentError::MissingFrontmatter { .. }),
+            "{err}"
+        );
+
+        let err = load(&[("a.md", "+++\ntitle = \"A\"\ndate = 2025-05-01\n")]).unwrap_err();
+        assert!(
+            matches!(err, ContentError::MissingFrontmatter { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn bad_toml() {
+        let err = load(&[("a.md", "+++\ntitle = A\n+++\n")]).unwrap_err();
+        assert!(matches!(err, ContentError::Frontmatter { .. }), "{err}");
+    }
+
+    #[test]
+    fn bad_yaml() {
+        let err = load(&[("a.md", "---\ntitle: [A\ndate: 2025-05-01\n---\n")]).unwrap_err();
+        assert!(matches!(err, ContentError::Frontmatter { .. }), "{err}");
+
+        let err = load(&[("a.md", "---\ntitle: A\ndate: someday\n---\n")]).unwrap_err();
+        assert!(matches!(err, ContentError::Frontmatter { .. }), "{err}");
+    }
+
+    #[test]
+    fn toml_native_date() {
+        let store = load(&[(
+            "hello.md",
+            "+++\ntitle = \"Hello\"\ndate = 2025-05-01\ntags = [\"meta\"]\n+++\nBody in *Markdown*.\n",
+        )])
+        .unwrap();
+        let post = store.get("hello").unwrap();
+        assert_eq!(post.meta.date, NaiveDate::from_ymd_opt(2025, 5, 1).unwrap());
+        assert_eq!(post.meta.tags, ["meta"]);
+        assert_eq!(post.html, "<p>Body in <em>Markdown</em>.</p>\n");
+    }
+
+    #[test]
+    fn yaml_frontmatter() {
+        let store = load(&[(
+            "hello.md",
+            "---\ntitle: Hello\ndate: 2025-05-01\nslug: hi\n---\nBody\n",
+        )])
+        .unwrap();
+        assert_eq!(store.get("hi").unwrap().meta.title, "Hello");
+        assert!(store.get("hello").is_none());
+    }
+
+    #[test]
+    fn invalid_slug() {
+        let err = load(&[(
+            "a.md",
+            "+++\ntitle = \"A\"\ndate = 2025-05-01\nslug = \"Not A Slug\"\n+++\n",
+        )])
+        .unwrap_err();
+        assert!(
+            matches!(&err, ContentError::InvalidSlug { slug, .. } if slug == "Not A Slug"),
+            "{err}"
+        );
+
+        let err =
+            load(&[("Upper.md", "+++\ntitle = \"A\"\ndate = 2025-05-01\n+++\n")]).unwrap_err();
+        assert!(matches!(err, ContentError::InvalidSlug { .. }), "{err}");
+    }
+
+    #[test]
+    fn slug_collision_with_draft() {
+        let err = load(&[
+            (
+                "a.md",
+                "+++\ntitle = \"A\"\ndate = 2025-05-01\nslug = \"same\"\ndraft = true\n+++\n",
+            ),
+            (
+                "b.md",
+                "+++\ntitle = \"B\"\ndate = 2025-05-02\nslug = \"same\"\n+++\n",
+            ),
+        ])
+        .unwrap_err();
+        match err {
+            ContentError::SlugCollision {
+                slug,
+                first,
+                second,
+            } => {
+                assert_eq!(slug, "same");
+                assert!(first.ends_with("a.md"));
+                assert!(second.ends_with("b.md"));
+            }
+            err => panic!("expected a collision, got {err}"),
+        }
+    }
+
+    #[test]
+    fn drafts_are_excluded() {
+        let store = load(&[
+            (
+                "draft.md",
+                "+++\ntitle = \"Draft\"\ndate = 2025-05-03\ndraft = true\n+++\n",
+            ),
+            ("old.md", "+++\ntitle = \"Old\"\ndate = 2025-05-01\n+++\n"),
+            ("new.md", "+++\ntitle = \"New\"\ndate = 2025-05-02\n+++\n"),
+        ])
+        .unwrap();
+        let slugs: Vec<_> = store
+            .posts()
+            .iter()
+            .map(|post| post.meta.slug.as_str())
+            .collect();
+        assert_eq!(slugs, ["new", "old"]);
+        assert!(store.get("draft").is_none());
+    }
+
+    #[test]
+    fn missing_dir_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PostStore::load(&dir.path().join("nope")).unwrap();
+        assert!(store.posts().is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+//! Error responses.
+//!
+//! Failed `/api` requests answer RFC 9457 problem details, see [`ApiError`],
+//! and failed browser navigations get an HTML page. Handlers that only have a
+//! status, like the static files, axum's rejections or a caught panic, leave
+//! the body empty or plain text and [`pages`] turns it into one of the two,
+//! so every error looks the same whatever produced it.
+//!
+//! Neither ever says more than the status and a detail meant for clients:
+//! what actually failed, like an io error with its path, is only logged.
+
+use std::any::Any;
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use shared::api::Problem;
+
+use crate::feed::escape_xml;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Plain text bodies longer than this are not taken as the detail.
+const MAX_DETAIL_LEN: usize = 1024;
+
+/// An `/api` error, answered as `application/problem+json`.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    status: StatusCode,
+    detail: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            detail: None,
+        }
+    }
+
+    /// Add what went wrong, which clients get to see.
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// The body, for answers that add members of their own to it.
+    pub fn problem(&self) -> Problem {
+        Problem {
+            kind: "about:blank".to_owned(),
+            title: self
+                .status
+                .canonical_reason()
+                .unwrap_or("Error")
+                .to_owned(),
+            status: self.status.as_u16(),
+            detail: self.detail.clone(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        problem_response(self.status, self.problem())
+    }
+}
+
+/// `body` as `application/problem+json`, for bodies extending [`Problem`].
+pub fn problem_response(status: StatusCode, body: impl Serialize) -> Response {
+    let mut res = (status, Json(body)).into_response();
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(PROBLEM_JSON),
+    );
+    res
+}
+
+/// Middleware for the fallback, answering every `/api` path that no route
+/// matched, so they do not end up at the frontend. A route for them would be
+/// one the OpenAPI document does not have.
+pub async fn api_not_found(req: Request, next: Next) -> Response {
+    if is_api(req.uri().path()) {
+        return ApiError::new(StatusCode::NOT_FOUND).into_response();
+    }
+    next.run(req).await
+}
+
+/// For `CatchPanicLayer`: a bare 500 that [`pages`] fills in.
+pub fn panic_response(err: Box<dyn Any + Send + 'static>) -> Response {
+    let message = err
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| err.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    log::error!("handler panicked: {message}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// Middleware giving error responses without a body of their own, or with
+/// a plain text one, the body that fits the request.
+pub async fn pages(req: Request, next: Next) -> Response {
+    let api = is_api(req.uri().path());
+    let navigation = !api && is_page_load(&req);
+    let res = next.run(req).await;
+    let status = res.status();
+    if !(status.is_client_error() || status.is_server_error()) || !(api || navigation) {
+        return res;
+    }
+    let plain = match res.headers().get(header::CONTENT_TYPE) {
+        None => false,
+        Some(value) if value.as_bytes().starts_with(b"text/plain") => true,
+        // already has a body meant for clients
+        Some(_) => return res,
+    };
+
+    let (parts, body) = res.into_parts();
+    let detail = if plain {
+        axum::body::to_bytes(body, MAX_DETAIL_LEN)
+            .await
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes.into()).ok())
+            .map(|text| text.trim().to_owned())
+            .filter(|text| !text.is_empty())
+    } else {
+        None
+    };
+    let mut res = if api {
+        let mut err = ApiError::new(status);
+        err.detail = detail;
+        err.into_response()
+    } else {
+        error_page(status, detail.as_deref())
+    };
+    // keeping the likes of `Retry-After`, `Allow` and `WWW-Authenticate`
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            res.headers_mut().append(name, value.clone());
+        }
+    }
+    res
+}
+
+fn is_api(path: &str) -> bool {
+    path == "/api" || path.starts_with("/api/")
+}
+
+fn is_page_load(req: &Request) -> bool {
+    (req.method() == Method::GET || req.method() == Method::HEAD)
+        && req
+            .headers()
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.contains("text/html"))
+}
+
+fn error_page(status: StatusCode, detail: Option<&str>) -> Response {
+    let title = format!(
+        "{} {}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("Error")
+    );
+    let message = match detail {
+        Some(detail) => escape_xml(detail),
+        None if status.is_server_error() => {
+            "Something went wrong on our side, please try again later.".to_owned()
+        }
+        None if status == StatusCode::NOT_FOUND => "There is nothing here.".to_owned(),
+        None if status == StatusCode::TOO_MANY_REQUESTS => {
+            "You sent a lot of requests, please try again later.".to_owned()
+        }
+        None => "The request could not be handled.".to_owned(),
+    };
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 32rem; margin: 20vh auto; padding: 0 1rem; \
+         text-align: center; color: #333; }}\n\
+         h1 {{ font-size: 3rem; margin-bottom: 0.5rem; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{message}</p>\n\
+         <p><a href=\"/\">Back to the start page</a></p>\n</body>\n</html>\n"
+    );
+    let mut res = Html(Body::from(html)).into_response();
+    *res.status_mut() = status;
+    res
+}
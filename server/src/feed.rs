@@ -0,0 +1,182 @@
+//! RSS 2.0, Atom and JSON Feed documents generated from the post store.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde_json::json;
+use shared::routes::Route;
+
+use crate::content::{Post, PostStore};
+
+const FEED_TITLE: &str = "Posts";
+
+#[derive(Clone)]
+struct FeedState {
+    posts: Arc<PostStore>,
+    /// absolute site url without a trailing slash
+    base_url: String,
+}
+
+impl FeedState {
+    fn post_url(&self, post: &Post) -> String {
+        let route = Route::Post {
+            slug: post.meta.slug.clone(),
+        };
+        format!("{}{}", self.base_url, route.path())
+    }
+
+    /// The newest post date, which is when the feed last changed.
+    fn updated(&self) -> DateTime<Utc> {
+        self.posts
+            .posts()
+            .first()
+            .map(|post| midnight_utc(post.meta.date))
+            .unwrap_or_default()
+    }
+}
+
+pub fn router(posts: Arc<PostStore>, base_url: &str) -> Router {
+    let state = FeedState {
+        posts,
+        base_url: base_url.trim_end_matches('/').to_owned(),
+    };
+    Router::new()
+        .route("/feed.xml", get(rss))
+        .route("/atom.xml", get(atom))
+        .route("/feed.json", get(json_feed))
+        .with_state(state)
+}
+
+async fn rss(State(state): State<FeedState>) -> impl IntoResponse {
+    let base = escape_xml(&state.base_url);
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#);
+    let _ = write!(
+        xml,
+        "<title>{}</title><link>{base}/</link><description>{}</description>\
+         <atom:link href=\"{base}/feed.xml\" rel=\"self\" type=\"application/rss+xml\"/>\
+         <lastBuildDate>{}</lastBuildDate>",
+        escape_xml(FEED_TITLE),
+        escape_xml(FEED_TITLE),
+        state.updated().to_rfc2822(),
+    );
+    for post in state.posts.posts() {
+        let url = escape_xml(&state.post_url(post));
+        let _ = write!(
+            xml,
+            "<item><title>{}</title><link>{url}</link><guid isPermaLink=\"true\">{url}</guid>\
+             <pubDate>{}</pubDate>",
+            escape_xml(&post.meta.title),
+            midnight_utc(post.meta.date).to_rfc2822(),
+        );
+        for tag in &post.meta.tags {
+            let _ = write!(xml, "<category>{}</category>", escape_xml(tag));
+        }
+        let _ = write!(
+            xml,
+            "<description>{}</description></item>",
+            escape_xml(&post.html)
+        );
+    }
+    xml.push_str("</channel></rss>");
+
+    (
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        xml,
+    )
+}
+
+async fn atom(State(state): State<FeedState>) -> impl IntoResponse {
+    let base = escape_xml(&state.base_url);
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = write!(
+        xml,
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\"><id>{base}/</id><title>{}</title>\
+         <updated>{}</updated><link href=\"{base}/atom.xml\" rel=\"self\"/>\
+         <link href=\"{base}/\" rel=\"alternate\"/>",
+        escape_xml(FEED_TITLE),
+        state.updated().to_rfc3339(),
+    );
+    for post in state.posts.posts() {
+        let url = escape_xml(&state.post_url(post));
+        let date = midnight_utc(post.meta.date).to_rfc3339();
+        let _ = write!(
+            xml,
+            "<entry><id>{url}</id><title>{}</title><updated>{date}</updated>\
+             <published>{date}</published><link href=\"{url}\" rel=\"alternate\"/>",
+            escape_xml(&post.meta.title),
+        );
+        for tag in &post.meta.tags {
+            let _ = write!(xml, "<category term=\"{}\"/>", escape_xml(tag));
+        }
+        let _ = write!(
+            xml,
+            "<content type=\"html\">{}</content></entry>",
+            escape_xml(&post.html)
+        );
+    }
+    xml.push_str("</feed>");
+
+    (
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        xml,
+    )
+}
+
+async fn json_feed(State(state): State<FeedState>) -> impl IntoResponse {
+    let base = &state.base_url;
+    let items: Vec<_> = state
+        .posts
+        .posts()
+        .iter()
+        .map(|post| {
+            let url = state.post_url(post);
+            json!({
+                "id": url,
+                "url": url,
+                "title": post.meta.title,
+                "content_html": post.html,
+                "date_published": midnight_utc(post.meta.date).to_rfc3339(),
+                "tags": post.meta.tags,
+            })
+        })
+        .collect();
+    let feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": FEED_TITLE,
+        "home_page_url": format!("{base}/"),
+        "feed_url": format!("{base}/feed.json"),
+        "items": items,
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/feed+json; charset=utf-8")],
+        feed.to_string(),
+    )
+}
+
+/// Posts only carry a date, so they are published at the start of that day.
+fn midnight_utc(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(NaiveTime::MIN).and_utc()
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,173 @@
+//! `/api/health`, `/api/ready` and `/api/version`, for uptime checks and
+//! for finding out which build is deployed.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::DateTime;
+use shared::api::{Health, Ready, Version};
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+/// Something the server needs before it can answer requests, like a store
+/// it loads from.
+pub trait ReadinessCheck: Send + Sync {
+    /// The key of the check in the `/api/ready` response.
+    fn name(&self) -> &'static str;
+
+    /// `Err` holds why the server is not ready. It is shown to anyone
+    /// asking, so details like paths are only for the log.
+    fn check(&self) -> Result<(), String>;
+}
+
+/// The static dir exists and has an `index.html`.
+pub struct StaticDirCheck(pub PathBuf);
+
+impl ReadinessCheck for StaticDirCheck {
+    fn name(&self) -> &'static str {
+        "static_dir"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if !self.0.is_dir() {
+            log::warn!("static dir {} is not a directory", self.0.display());
+            return Err("static dir missing".to_owned());
+        }
+        let index = self.0.join("index.html");
+        if !index.is_file() {
+            log::warn!("{} is missing", index.display());
+            return Err("index.html missing".to_owned());
+        }
+        Ok(())
+    }
+}
+
+pub fn router(checks: Vec<Arc<dyn ReadinessCheck>>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(health))
+        .routes(routes!(ready))
+        .routes(routes!(version))
+        .with_state(Arc::<[_]>::from(checks))
+}
+
+/// Answers as long as the process can serve requests at all.
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "status",
+    responses((status = OK, body = Health))
+)]
+async fn health() -> impl IntoResponse {
+    Json(Health {
+        status: "ok".to_owned(),
+    })
+}
+
+/// Whether every readiness check passes.
+#[utoipa::path(
+    get,
+    path = "/api/ready",
+    tag = "status",
+    responses(
+        (status = OK, body = Ready),
+        (status = SERVICE_UNAVAILABLE, description = "A check failed", body = Ready),
+    )
+)]
+async fn ready(State(checks): State<Arc<[Arc<dyn ReadinessCheck>]>>) -> impl IntoResponse {
+    let mut ready = true;
+    let results = checks
+        .iter()
+        .map(|check| {
+            let result = match check.check() {
+                Ok(()) => "ok".to_owned(),
+                Err(reason) => {
+                    log::warn!("not ready, {}: {reason}", check.name());
+                    ready = false;
+                    reason
+                }
+            };
+            (check.name().to_owned(), result)
+        })
+        .collect();
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = Ready {
+        ready,
+        checks: results,
+    };
+    (status, Json(body))
+}
+
+/// The build that is deployed.
+#[utoipa::path(
+    get,
+    path = "/api/version",
+    tag = "status",
+    responses((status = OK, body = Version))
+)]
+async fn version() -> impl IntoResponse {
+    let built_at = env!("BUILD_UNIX_TIME")
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .map(|built_at| built_at.to_rfc3339())
+        .unwrap_or_default();
+    Json(Version {
+        git_sha: env!("BUILD_GIT_SHA").to_owned(),
+        built_at,
+        server: env!("CARGO_PKG_VERSION").to_owned(),
+        shared: shared::VERSION.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use shared::api::Ready;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn ready(static_dir: PathBuf) -> (StatusCode, Ready) {
+        let (app, _) = router(vec![Arc::new(StaticDirCheck(static_dir))]).split_for_parts();
+        let req = Request::get("/api/ready").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn ready_with_index() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "").unwrap();
+        let (status, body) = ready(dir.path().to_owned()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.ready);
+        assert_eq!(body.checks["static_dir"], "ok");
+    }
+
+    #[tokio::test]
+    async fn not_ready_without_saying_where() {
+        let dir = tempfile::tempdir().unwrap();
+        let (status, body) = ready(dir.path().to_owned()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!body.ready);
+        assert_eq!(body.checks["static_dir"], "index.html missing");
+
+        let (status, body) = ready(dir.path().join("dist")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.checks["static_dir"], "static dir missing");
+    }
+}
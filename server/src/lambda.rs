@@ -0,0 +1,665 @@
+//! Running the app as a vercel function: [`LambdaLayer`] turns vercel's
+//! requests into the app's and the app's responses into vercel's.
+
+use axum::body::{BodyDataStream, HttpBody};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::IntoResponse;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use lambda_runtime::{FunctionResponse, MetadataPrelude, StreamResponse};
+use std::sync::Arc;
+use std::{future::Future, pin::Pin};
+use tower::Layer;
+use tower_service::Service;
+use vercel_runtime::response::EventResponse;
+use vercel_runtime::{process_response, Body, Error, Request};
+
+use crate::error::ApiError;
+use crate::Config;
+
+/// The most vercel takes as the body of a function's request or response,
+/// after base64 encoding for binary ones.
+pub const MAX_BODY: usize = 4_500_000;
+/// The most Lambda takes as the body of a streamed response.
+const MAX_STREAMED_BODY: usize = 20 * 1024 * 1024;
+
+/// Either the whole response, or its head with a stream of the body.
+pub type LambdaResponse = FunctionResponse<EventResponse, BodyDataStream>;
+
+/// The media types answered as text unless [`LambdaLayer::text_types`]
+/// names others.
+pub const DEFAULT_TEXT_TYPES: &[&str] = &[
+    "text/*",
+    "application/json",
+    "application/*+json",
+    "application/javascript",
+    "application/xml",
+    "application/*+xml",
+    "image/svg+xml",
+];
+
+/// Runs an axum app on vercel's requests.
+#[derive(Clone, Debug)]
+pub struct LambdaLayer {
+    /// without a trailing slash, never empty
+    strip_prefix: Option<Arc<str>>,
+    /// lowercase
+    text_types: Arc<[String]>,
+    /// `None` to buffer every body
+    stream_threshold: Option<usize>,
+}
+
+impl Default for LambdaLayer {
+    fn default() -> Self {
+        Self {
+            strip_prefix: None,
+            text_types: DEFAULT_TEXT_TYPES.iter().map(|t| t.to_string()).collect(),
+            stream_threshold: None,
+        }
+    }
+}
+
+impl LambdaLayer {
+    /// Remove `prefix`, like a deployment stage, from the start of every
+    /// path before the app sees it, so with `/prod` a request for
+    /// `/prod/api/posts?tag=a` reaches the app as `/api/posts?tag=a`. Only
+    /// whole segments match and other paths pass unchanged.
+    pub fn strip_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        self.strip_prefix = (!prefix.is_empty()).then(|| prefix.into());
+        self
+    }
+
+    /// Answer bodies of these media types as text, and all others base64
+    /// encoded as binary. Patterns like `text/*` and `application/*+json`
+    /// match whole families. Bodies with a `Content-Encoding` or a charset
+    /// other than UTF-8 are binary whatever their type.
+    pub fn text_types<I, T>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        self.text_types = types
+            .into_iter()
+            .map(|t| t.as_ref().trim().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Stream response bodies to the client as they are produced, which
+    /// Server-Sent Events and large downloads need, except those known to be
+    /// at most `threshold` bytes, which are sent in one piece as before. The
+    /// function must have response streaming enabled on vercel.
+    pub fn streaming(mut self, threshold: usize) -> Self {
+        self.stream_threshold = Some(threshold);
+        self
+    }
+}
+
+/// The layer as `config` sets it up.
+pub fn layer(config: &Config) -> LambdaLayer {
+    let mut layer = LambdaLayer::default();
+    if let Some(prefix) = &config.vercel_strip_prefix {
+        layer = layer.strip_prefix(prefix);
+    }
+    if let Some(types) = &config.vercel_text_types {
+        layer = layer.text_types(types);
+    }
+    if let Some(threshold) = config.vercel_stream_threshold {
+        layer = layer.streaming(threshold);
+    }
+    layer
+}
+
+impl<S> Layer<S> for LambdaLayer {
+    type Service = LambdaService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LambdaService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LambdaService<S> {
+    inner: S,
+    layer: LambdaLayer,
+}
+
+impl<S> Service<Request> for LambdaService<S>
+where
+    S: Service<axum::http::Request<axum::body::Body>>,
+    S::Response: axum::response::IntoResponse + Send + 'static,
+    S::Error: std::error::Error + Send + Sync + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = LambdaResponse;
+    type Error = Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+        let body = match body {
+            Body::Empty => axum::body::Body::default(),
+            Body::Text(t) => t.into(),
+            Body::Binary(v) => v.into(),
+        };
+
+        if let Some(prefix) = &self.layer.strip_prefix {
+            match strip_prefix(&parts.uri, prefix) {
+                Ok(uri) => parts.uri = uri,
+                Err(err) => {
+                    log::warn!("failed to strip {prefix} from {}: {err}", parts.uri);
+                    let resp = ApiError::new(StatusCode::BAD_REQUEST)
+                        .detail("The request URI is malformed.")
+                        .into_response();
+                    return Box::pin(into_lambda(resp, self.layer.clone()));
+                }
+            }
+        }
+
+        let request = axum::http::Request::from_parts(parts, body);
+
+        let fut = self.inner.call(request);
+        let layer = self.layer.clone();
+        Box::pin(async move { into_lambda(fut.await?.into_response(), layer).await })
+    }
+}
+
+/// `uri` with `prefix` removed from its path. The rest of the path and the
+/// query are kept as they are, percent-encoding included.
+fn strip_prefix(uri: &Uri, prefix: &str) -> Result<Uri, axum::http::Error> {
+    let Some(rest) = uri
+        .path()
+        .strip_prefix(prefix)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+    else {
+        return Ok(uri.clone());
+    };
+    let path = if rest.is_empty() { "/" } else { rest };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_owned(),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+async fn into_lambda(
+    resp: axum::response::Response,
+    layer: LambdaLayer,
+) -> Result<LambdaResponse, Error> {
+    let size = resp.body().size_hint();
+    let stream = layer
+        .stream_threshold
+        .is_some_and(|threshold| size.exact().is_none_or(|len| len > threshold as u64));
+    let limit = if stream { MAX_STREAMED_BODY } else { MAX_BODY };
+    if size.lower() > limit as u64 {
+        log::error!("response of {} bytes is over the limit of {limit}", size.lower());
+        return buffer(too_large(), &layer.text_types).await;
+    }
+
+    if stream {
+        let (parts, body) = resp.into_parts();
+        let mut headers = parts.headers;
+        // the prelude carries cookies on their own
+        let cookies = headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|cookie| String::from_utf8_lossy(cookie.as_bytes()).into_owned())
+            .collect();
+        headers.remove(header::SET_COOKIE);
+        let metadata_prelude = MetadataPrelude {
+            status_code: parts.status,
+            headers,
+            cookies,
+        };
+        // past the limit the stream fails, which ends the response early
+        let body = Limited::new(body, limit).map_err(move |err| {
+            if err.is::<LengthLimitError>() {
+                log::error!("cut off streamed response at the limit of {limit} bytes");
+            }
+            err
+        });
+        let body = axum::body::Body::new(body);
+        return Ok(FunctionResponse::StreamingResponse(StreamResponse {
+            metadata_prelude,
+            stream: body.into_data_stream(),
+        }));
+    }
+    buffer(resp, &layer.text_types).await
+}
+
+/// A 502 for responses too large for vercel to pass on.
+fn too_large() -> axum::response::Response {
+    ApiError::new(StatusCode::BAD_GATEWAY)
+        .detail("The response is too large to be sent.")
+        .into_response()
+}
+
+async fn buffer(
+    resp: axum::response::Response,
+    text_types: &[String],
+) -> Result<LambdaResponse, Error> {
+    let (parts, body) = resp.into_parts();
+    let bytes = match Limited::new(body, MAX_BODY).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) if err.is::<LengthLimitError>() => {
+            log::error!("response is over the limit of {MAX_BODY} bytes");
+            return Box::pin(buffer(too_large(), text_types)).await;
+        }
+        Err(err) => return Err(err),
+    };
+    let body = if bytes.is_empty() {
+        Body::Empty
+    } else if is_text(&parts.headers, text_types) {
+        match String::from_utf8(bytes.into()) {
+            Ok(text) => Body::Text(text),
+            // claims to be UTF-8 but is not, so at least send it unchanged
+            Err(err) => Body::Binary(err.into_bytes()),
+        }
+    } else {
+        Body::Binary(bytes.into())
+    };
+    // binary bodies go base64 encoded, which makes them a third larger
+    if let Body::Binary(binary) = &body {
+        if binary.len().div_ceil(3) * 4 > MAX_BODY {
+            log::error!(
+                "response of {} bytes is over the limit of {MAX_BODY} once base64 encoded",
+                binary.len()
+            );
+            return Box::pin(buffer(too_large(), text_types)).await;
+        }
+    }
+    let resp = axum::response::Response::from_parts(parts, body);
+    Ok(FunctionResponse::BufferedResponse(process_response(resp)))
+}
+
+/// Whether a body with `headers` goes to vercel as text: it has one of
+/// `text_types`, no `Content-Encoding`, and no charset but UTF-8.
+fn is_text(headers: &HeaderMap, text_types: &[String]) -> bool {
+    let encoded = headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|value| value.as_bytes() != b"identity");
+    if encoded {
+        return false;
+    }
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mut params = content_type.split(';');
+    let essence = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let utf8 = params
+        .filter_map(|param| param.split_once('='))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .all(|(_, charset)| {
+            let charset = charset.trim().trim_matches('"');
+            charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("us-ascii")
+        });
+    utf8 && text_types
+        .iter()
+        .any(|pattern| media_type_matches(pattern, &essence))
+}
+
+/// `pattern` being like `text/html`, `text/*` or `application/*+json`.
+fn media_type_matches(pattern: &str, essence: &str) -> bool {
+    let (Some((pattern_type, pattern_sub)), Some((essence_type, essence_sub))) =
+        (pattern.split_once('/'), essence.split_once('/'))
+    else {
+        return false;
+    };
+    if pattern_type != "*" && pattern_type != essence_type {
+        return false;
+    }
+    match pattern_sub.strip_prefix('*') {
+        Some(suffix) => essence_sub.ends_with(suffix),
+        None => pattern_sub == essence_sub,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// `req` through `layer` to an app answering with `respond`.
+    async fn lambda<H, T>(layer: LambdaLayer, respond: H, req: Request) -> LambdaResponse
+    where
+        H: axum::handler::Handler<T, ()>,
+        T: 'static,
+    {
+        let app = Router::new().fallback(respond);
+        layer.layer(app).oneshot(req).await.unwrap()
+    }
+
+    /// [`lambda`], for responses that are not streamed.
+    async fn call<H, T>(layer: LambdaLayer, respond: H, req: Request) -> EventResponse
+    where
+        H: axum::handler::Handler<T, ()>,
+        T: 'static,
+    {
+        match lambda(layer, respond, req).await {
+            FunctionResponse::BufferedResponse(res) => res,
+            FunctionResponse::StreamingResponse(_) => panic!("response was streamed"),
+        }
+    }
+
+    fn request(uri: &str) -> Request {
+        axum::http::Request::builder()
+            .uri(uri)
+            .body(Body::Empty)
+            .unwrap()
+    }
+
+    /// The path and query the app saw for `path` with `/prod` stripped.
+    async fn stripped(path: &str) -> String {
+        let echo = |uri: Uri| async move { uri.path_and_query().unwrap().to_string() };
+        let uri = format!("https://example.vercel.app{path}");
+        let layer = LambdaLayer::default().strip_prefix("/prod/");
+        let res = call(layer, echo, request(&uri)).await;
+        assert_eq!(res.status_code, 200);
+        match res.body {
+            Some(Body::Text(text)) => text,
+            other => panic!("not text: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn strip_prefix_by_whole_segments() {
+        assert_eq!(stripped("/prod/api/posts").await, "/api/posts");
+        assert_eq!(stripped("/prod/prod/a").await, "/prod/a");
+        assert_eq!(stripped("/production/api").await, "/production/api");
+        assert_eq!(stripped("/products").await, "/products");
+        assert_eq!(stripped("/api/prod").await, "/api/prod");
+    }
+
+    #[tokio::test]
+    async fn strip_bare_prefix() {
+        assert_eq!(stripped("/prod").await, "/");
+        assert_eq!(stripped("/prod/").await, "/");
+        assert_eq!(stripped("/prod?tag=a").await, "/?tag=a");
+    }
+
+    #[tokio::test]
+    async fn strip_prefix_keeps_query_and_encoding() {
+        assert_eq!(
+            stripped("/prod/posts/gr%C3%BC%C3%9Fe%2Fa?tag=a%20b&next=%2Fprod").await,
+            "/posts/gr%C3%BC%C3%9Fe%2Fa?tag=a%20b&next=%2Fprod"
+        );
+        assert_eq!(stripped("/prod/a?").await, "/a?");
+    }
+
+    #[test]
+    fn strip_prefix_keeps_authority() {
+        let uri = "https://example.vercel.app/prod/a?b".parse().unwrap();
+        assert_eq!(
+            strip_prefix(&uri, "/prod").unwrap(),
+            "https://example.vercel.app/a?b"
+        );
+    }
+
+    /// The body vercel gets for an app answering with `headers` and `body`,
+    /// decoded again, and whether it was sent as text.
+    async fn round_trip(headers: &[(header::HeaderName, &str)], body: Vec<u8>) -> (Vec<u8>, bool) {
+        let headers: HeaderMap = headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.parse().unwrap()))
+            .collect();
+        let respond = move || {
+            let headers = headers.clone();
+            let body = body.clone();
+            async move { (headers, body) }
+        };
+        let res = call(LambdaLayer::default(), respond, request("/")).await;
+
+        let json = serde_json::to_value(&res).unwrap();
+        let body = json["body"].as_str().unwrap();
+        match json.get("encoding").and_then(|encoding| encoding.as_str()) {
+            None => (body.as_bytes().to_vec(), true),
+            Some("base64") => {
+                use base64::Engine;
+                let decoded = base64::engine::general_purpose::STANDARD.decode(body);
+                (decoded.unwrap(), false)
+            }
+            Some(other) => panic!("unknown encoding {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn png_round_trip() {
+        let png =
+            b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0\x1f\x15\xc4\x89";
+        let (body, text) = round_trip(&[(header::CONTENT_TYPE, "image/png")], png.to_vec()).await;
+        assert!(!text);
+        assert_eq!(body, png);
+    }
+
+    #[tokio::test]
+    async fn gzip_json_round_trip() {
+        use std::io::Write;
+        let json = br#"{"posts":[]}"#;
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(json).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        let headers = [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_ENCODING, "gzip"),
+        ];
+        let (body, text) = round_trip(&headers, gzip.clone()).await;
+        assert!(!text);
+        assert_eq!(body, gzip);
+    }
+
+    #[tokio::test]
+    async fn utf8_json_round_trip() {
+        let json = r#"{"title":"Grüße ✓","body":"\"quoted\"\n"}"#;
+        let headers = [(header::CONTENT_TYPE, "application/json")];
+        let (body, text) = round_trip(&headers, json.into()).await;
+        assert!(text);
+        assert_eq!(body, json.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn other_charset_round_trip() {
+        // `café` in latin-1, which is not UTF-8
+        let latin1 = b"caf\xe9".to_vec();
+        let headers = [(header::CONTENT_TYPE, "text/plain; charset=ISO-8859-1")];
+        let (body, text) = round_trip(&headers, latin1.clone()).await;
+        assert!(!text);
+        assert_eq!(body, latin1);
+    }
+
+    fn is_text_type(content_type: &str) -> bool {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        let text_types = LambdaLayer::default().text_types;
+        is_text(&headers, &text_types)
+    }
+
+    #[test]
+    fn charsets() {
+        assert!(is_text_type("text/html; charset=utf-8"));
+        assert!(is_text_type("text/html;charset=\"UTF-8\""));
+        assert!(is_text_type("text/plain; charset=us-ascii"));
+        assert!(is_text_type("Application/JSON"));
+        assert!(!is_text_type("text/plain; charset=iso-8859-1"));
+        assert!(!is_text_type("text/plain; charset=utf-16"));
+        assert!(!is_text_type("image/png"));
+    }
+
+    #[test]
+    fn content_encoding_is_binary() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "text/css".parse().unwrap());
+        let text_types = LambdaLayer::default().text_types;
+        assert!(is_text(&headers, &text_types));
+        headers.insert(header::CONTENT_ENCODING, "identity".parse().unwrap());
+        assert!(is_text(&headers, &text_types));
+        headers.insert(header::CONTENT_ENCODING, "br".parse().unwrap());
+        assert!(!is_text(&headers, &text_types));
+        // without a type there is no telling
+        assert!(!is_text(&HeaderMap::new(), &text_types));
+    }
+
+    #[test]
+    fn media_type_wildcards() {
+        assert!(media_type_matches("text/*", "text/html"));
+        assert!(media_type_matches("text/*", "text/event-stream"));
+        assert!(!media_type_matches("text/*", "texts/html"));
+        assert!(media_type_matches("*/*", "image/png"));
+        let json_family = "application/*+json";
+        assert!(media_type_matches(json_family, "application/problem+json"));
+        assert!(media_type_matches(json_family, "application/ld+json"));
+        assert!(!media_type_matches(json_family, "application/json"));
+        assert!(!media_type_matches(json_family, "text/x+json"));
+        assert!(media_type_matches("application/json", "application/json"));
+        assert!(!media_type_matches("application/json", "application/jsonl"));
+        assert!(!media_type_matches("text/*", "text"));
+        assert!(!media_type_matches("text", "text"));
+    }
+
+    #[test]
+    fn configured_text_types() {
+        let layer = LambdaLayer::default().text_types([" Application/WASM ", "font/*"]);
+        let is_text_type = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+            is_text(&headers, &layer.text_types)
+        };
+        assert!(is_text_type("application/wasm"));
+        assert!(is_text_type("font/woff2"));
+        assert!(!is_text_type("text/html"));
+    }
+
+    #[test]
+    fn empty_prefix_strips_nothing() {
+        for prefix in ["", "/"] {
+            let layer = LambdaLayer::default().strip_prefix(prefix);
+            assert!(layer.strip_prefix.is_none(), "{prefix}");
+        }
+    }
+
+    /// `len` bytes, with their length known up front or not.
+    fn body(len: usize, known: bool) -> axum::body::Body {
+        let body = axum::body::Body::from(vec![b'a'; len]);
+        if known {
+            body
+        } else {
+            axum::body::Body::from_stream(body.into_data_stream())
+        }
+    }
+
+    fn text_response(body: axum::body::Body) -> axum::response::Response {
+        ([(header::CONTENT_TYPE, "text/plain")], body).into_response()
+    }
+
+    #[tokio::test]
+    async fn known_small_bodies_are_buffered() {
+        let layer = LambdaLayer::default().streaming(16);
+        for len in [0, 15, 16] {
+            let respond = move || async move { text_response(body(len, true)) };
+            let res = call(layer.clone(), respond, request("/")).await;
+            assert_eq!(res.status_code, 200);
+            match (len, res.body) {
+                (0, None) => {}
+                (_, Some(Body::Text(text))) => assert_eq!(text.len(), len),
+                (_, other) => panic!("{len} bytes came as {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn large_or_unknown_bodies_are_streamed() {
+        let layer = LambdaLayer::default().streaming(16);
+        for (len, known) in [(17, true), (3, false), (100_000, false)] {
+            let respond = move || async move {
+                let mut res = text_response(body(len, known));
+                res.headers_mut()
+                    .append(header::SET_COOKIE, "a=1".parse().unwrap());
+                res
+            };
+            let FunctionResponse::StreamingResponse(res) =
+                lambda(layer.clone(), respond, request("/")).await
+            else {
+                panic!("{len} bytes were not streamed");
+            };
+            let prelude = res.metadata_prelude;
+            assert_eq!(prelude.status_code, StatusCode::OK);
+            assert_eq!(prelude.cookies, ["a=1"]);
+            assert!(!prelude.headers.contains_key(header::SET_COOKIE));
+            let streamed = axum::body::Body::from_stream(res.stream);
+            let bytes = axum::body::to_bytes(streamed, usize::MAX).await.unwrap();
+            assert_eq!(bytes.len(), len);
+        }
+    }
+
+    #[tokio::test]
+    async fn streamed_body_over_the_limit_fails() {
+        let layer = LambdaLayer::default().streaming(16);
+        let respond = || async { text_response(body(MAX_STREAMED_BODY + 1, false)) };
+        let FunctionResponse::StreamingResponse(res) = lambda(layer, respond, request("/")).await
+        else {
+            panic!("not streamed");
+        };
+        let streamed = axum::body::Body::from_stream(res.stream);
+        assert!(axum::body::to_bytes(streamed, usize::MAX).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn known_bodies_over_the_limit_are_refused() {
+        let cases = [
+            (LambdaLayer::default(), MAX_BODY + 1),
+            (LambdaLayer::default().streaming(16), MAX_STREAMED_BODY + 1),
+        ];
+        for (layer, len) in cases {
+            let respond = move || async move { text_response(body(len, true)) };
+            let res = call(layer, respond, request("/")).await;
+            assert_eq!(res.status_code, 502, "{len}");
+            assert_eq!(
+                res.headers[header::CONTENT_TYPE],
+                "application/problem+json"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_buffered_body_over_the_limit_is_refused() {
+        let respond = || async { text_response(body(MAX_BODY + 1, false)) };
+        let res = call(LambdaLayer::default(), respond, request("/")).await;
+        assert_eq!(res.status_code, 502);
+    }
+
+    #[tokio::test]
+    async fn binary_limit_is_after_base64() {
+        // (raw length, type, status): the first fits as it is, but not once encoded
+        let cases = [
+            (MAX_BODY / 4 * 3 + 3, "application/octet-stream", 502),
+            (MAX_BODY / 4 * 3 + 3, "text/plain", 200),
+            (MAX_BODY / 4 * 3, "application/octet-stream", 200),
+        ];
+        for (len, content_type, status) in cases {
+            let respond =
+                move || async move { ([(header::CONTENT_TYPE, content_type)], vec![b'a'; len]) };
+            let res = call(LambdaLayer::default(), respond, request("/")).await;
+            assert_eq!(res.status_code, status, "{len} bytes of {content_type}");
+        }
+    }
+}
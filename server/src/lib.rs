@@ -1,77 +1,223 @@
-use axum::body::Body;
-use axum::http::{Response, StatusCode};
-use axum::{response::IntoResponse, routing::get, Router};
-use clap::Parser;
+use axum::routing::get_service;
+use axum::{middleware, response::IntoResponse, Extension, Json, Router};
+use shared::api::Hello;
+use std::fmt;
+use std::io;
 use std::path::PathBuf;
-use tokio::fs;
-use tower::{ServiceBuilder, ServiceExt};
-use tower_http::services::ServeDir;
+use std::sync::Arc;
+use tower::ServiceBuilder;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
+use utoipa::OpenApi;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
 
-// Setup the command line interface with clap.
-#[derive(Parser, Debug)]
-#[clap(name = "server", about = "A server for our wasm project!")]
-pub struct Opt {
-    /// set the log level
-    #[clap(short = 'l', long = "log", default_value = "debug")]
-    pub log_level: String,
+pub mod admin;
+mod auth;
+pub mod cache;
+pub mod compress;
+pub mod config;
+pub mod contact;
+pub mod content;
+pub mod error;
+pub mod feed;
+pub mod health;
+pub mod lambda;
+#[cfg(feature = "dev")]
+pub mod livereload;
+pub mod logging;
+pub mod metrics;
+pub mod openapi;
+pub mod ratelimit;
+pub mod request_id;
+pub mod security;
+pub mod shutdown;
+pub mod sitemap;
+pub mod spa;
+pub mod tls;
+pub mod vercel;
 
-    /// set the listen addr
-    #[clap(short = 'a', long = "addr", default_value = "::1")]
-    pub addr: String,
+pub use config::{Command, Config, Opt};
+pub use shutdown::Shutdown;
 
-    /// set the listen port
-    #[clap(short = 'p', long = "port", default_value = "8080")]
-    pub port: u16,
+/// Why the app could not be set up.
+#[derive(Debug)]
+pub enum SetupError {
+    Content(content::ContentError),
+    Robots {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Index {
+        static_dir: PathBuf,
+        source: io::Error,
+    },
+    Contact(contact::DeliveryError),
+    #[cfg(feature = "dev")]
+    LiveReload {
+        static_dir: PathBuf,
+        source: notify::Error,
+    },
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::Content(err) => write!(f, "failed to load posts: {err}"),
+            SetupError::Robots { path, source } => {
+                write!(f, "failed to read robots file {}: {source}", path.display())
+            }
+            SetupError::Index { static_dir, source } => write!(
+                f,
+                "no index.html in static dir {}, build the frontend first: {source}",
+                static_dir.display()
+            ),
+            SetupError::Contact(err) => write!(f, "failed to set up the contact form: {err}"),
+            #[cfg(feature = "dev")]
+            SetupError::LiveReload { static_dir, source } => {
+                write!(f, "failed to watch {}: {source}", static_dir.display())
+            }
+        }
+    }
+}
 
-    /// set the directory where static files are to be found
-    #[clap(long = "static-dir", default_value = "./dist")]
-    pub static_dir: String,
+impl std::error::Error for SetupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SetupError::Content(err) => Some(err),
+            SetupError::Contact(err) => Some(err),
+            SetupError::Robots { source, .. } | SetupError::Index { source, .. } => Some(source),
+            #[cfg(feature = "dev")]
+            SetupError::LiveReload { source, .. } => Some(source),
+        }
+    }
 }
 
-pub async fn setup_app(static_dir: String) -> Router {
-    let app = Router::new()
-        .route("/api/hello/", get(hello))
-        .fallback_service(get(|req: axum::http::Request<Body>| async move {
-            match ServeDir::new(&static_dir).oneshot(req).await {
-                Ok(res) => {
-                    let status = res.status();
-                    match status {
-                        StatusCode::NOT_FOUND => {
-                            let index_path = PathBuf::from(&static_dir).join("index.html");
-                            let index_content = match fs::read_to_string(index_path).await {
-                                Err(_) => {
-                                    let pwd = std::env::current_dir().unwrap();
-                                    return Response::builder()
-                                        .status(StatusCode::NOT_FOUND)
-                                        .body(Body::from(format!(
-                                            "index file not found at {}",
-                                            pwd.display()
-                                        )))
-                                        .unwrap();
-                                }
-                                Ok(index_content) => index_content,
-                            };
+/// Build the app. Long lived tasks it starts end when `shutdown` triggers.
+#[cfg_attr(not(feature = "dev"), allow(unused_variables))]
+pub async fn setup_app(config: &Config, shutdown: &Shutdown) -> Result<Router, SetupError> {
+    let posts = content::PostStore::load(&config.content_dir).map_err(SetupError::Content)?;
+    let posts = Arc::new(posts);
+    let robots = match &config.robots_txt {
+        None => None,
+        Some(path) => Some(
+            std::fs::read_to_string(path).map_err(|source| SetupError::Robots {
+                path: path.clone(),
+                source,
+            })?,
+        ),
+    };
+
+    #[allow(unused_mut)]
+    let mut spa =
+        spa::SpaFallback::new(&config.static_dir).map_err(|source| SetupError::Index {
+            static_dir: config.static_dir.clone(),
+            source,
+        })?;
+    #[allow(unused_mut)]
+    let mut app = Router::new();
 
-                            Response::builder()
-                                .status(StatusCode::OK)
-                                .body(Body::from(index_content))
-                                .unwrap()
-                        }
-                        _ => res.map(Body::new),
-                    }
+    #[cfg(feature = "dev")]
+    if config.dev {
+        let live =
+            livereload::LiveReload::watch(&config.static_dir, shutdown).map_err(|source| {
+                SetupError::LiveReload {
+                    static_dir: config.static_dir.clone(),
+                    source,
                 }
-                Err(err) => Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(Body::from(format!("error: {err}")))
-                    .expect("error response"),
-            }
-        }))
-        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()));
+            })?;
+        spa = spa.append_to_body(livereload::SCRIPT);
+        app = app.merge(livereload::router(live));
+    }
+
+    let mut static_files = get_service(spa);
+    if config.cache_headers {
+        let policy = cache::CachePolicy {
+            hashed_max_age: config.hashed_max_age,
+        };
+        static_files = static_files.layer(middleware::from_fn_with_state(policy, cache::apply));
+    }
+    let static_files = static_files.layer(middleware::from_fn(error::api_not_found));
+
+    // every /api route, so the OpenAPI document covers them all
+    let mut api = OpenApiRouter::with_openapi(openapi::ApiDoc::openapi())
+        .routes(routes!(hello))
+        .merge(health::router(vec![Arc::new(health::StaticDirCheck(
+            config.static_dir.clone(),
+        ))]))
+        .merge(security::router())
+        .merge(content::router(posts.clone()))
+        .merge(openapi::router(config.api_docs));
+    if let Some(contact) = &config.contact {
+        let delivery = contact::delivery(contact).map_err(SetupError::Contact)?;
+        let tokens = contact::FormTokens::new(contact.secret.as_deref());
+        api = api.merge(contact::router(delivery, tokens));
+    }
+    if let Some(token) = &config.admin_token {
+        api = api.merge(admin::router(token, logging::filter()));
+    }
+    let (api, doc) = api.split_for_parts();
+    let api = api.layer(Extension(openapi::Rendered::new(&doc)));
+
+    let app = app
+        .merge(api)
+        .merge(feed::router(posts.clone(), &config.base_url))
+        .merge(sitemap::router(
+            posts,
+            &config.base_url,
+            &config.static_dir,
+            robots,
+            config.contact.is_some(),
+        ))
+        .fallback_service(static_files)
+        .layer(CatchPanicLayer::custom(error::panic_response));
+
+    let app = if config.rate_limits.is_empty() {
+        app
+    } else {
+        let limits = ratelimit::RateLimits::new(&config.rate_limits, &config.trusted_proxies);
+        app.layer(middleware::from_fn_with_state(
+            Arc::new(limits),
+            ratelimit::limit,
+        ))
+    };
+    let app = app.layer(middleware::from_fn(error::pages));
+    let app = app.layer(middleware::from_fn_with_state(
+        Arc::new(config.security_headers.clone()),
+        security::apply,
+    ));
+    let app = match &config.metrics {
+        None => app,
+        Some(metrics_config) => {
+            let metrics = metrics::Metrics::default();
+            // layered before merging, so scrapes do not count themselves
+            app.layer(middleware::from_fn_with_state(
+                metrics.clone(),
+                metrics::record,
+            ))
+            .merge(metrics::router(metrics, metrics_config))
+        }
+    };
+    let app = app.layer(
+        ServiceBuilder::new()
+            .layer(middleware::map_request(request_id::drop_invalid))
+            .layer(SetRequestIdLayer::new(request_id::HEADER, MakeRequestUuid))
+            .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span))
+            .layer(PropagateRequestIdLayer::new(request_id::HEADER)),
+    );
 
-    app
+    Ok(app)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/hello/",
+    tag = "meta",
+    responses((status = OK, body = Hello))
+)]
 async fn hello() -> impl IntoResponse {
-    "hello from server +!"
+    Json(Hello {
+        message: "hello from server +!".to_owned(),
+    })
 }
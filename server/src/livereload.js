@@ -0,0 +1,26 @@
+// Injected into index.html by `server --dev`.
+(() => {
+    const url = (location.protocol === "https:" ? "wss://" : "ws://") + location.host + "/__livereload";
+    let seen = false;
+    const connect = () => {
+        const socket = new WebSocket(url);
+        // a reconnect means the server restarted, possibly with new code
+        socket.onopen = () => {
+            if (seen) location.reload();
+            seen = true;
+        };
+        socket.onmessage = (event) => {
+            if (event.data !== "css") {
+                location.reload();
+                return;
+            }
+            for (const link of document.querySelectorAll('link[rel="stylesheet"]')) {
+                const href = new URL(link.href);
+                href.searchParams.set("livereload", Date.now());
+                link.href = href;
+            }
+        };
+        socket.onclose = () => setTimeout(connect, 500);
+    };
+    connect();
+})();
@@ -0,0 +1,224 @@
+//! Reloads browsers when the static dir changes, for `server --dev`.
+//!
+//! The served `index.html` gets a small script that connects to
+//! `/__livereload`. Every change in the static dir is pushed to it: a change
+//! to stylesheets only re-fetches them, anything else reloads the page. When
+//! the server restarts the socket reconnects and the page reloads too.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::broadcast;
+
+use crate::Shutdown;
+
+pub const PATH: &str = "/__livereload";
+
+/// The `<script>` element to add to `index.html`.
+pub const SCRIPT: &str = concat!("<script>", include_str!("livereload.js"), "</script>");
+
+/// Changes that arrive this close together are sent as one message, so a
+/// rebuild that writes many files reloads once, and only after `index.html`
+/// itself has been reloaded.
+const SETTLE: Duration = Duration::from_millis(150);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Css,
+    Other,
+}
+
+#[derive(Clone)]
+pub struct LiveReload {
+    changes: broadcast::Sender<Change>,
+    shutdown: Shutdown,
+    _watcher: Arc<RecommendedWatcher>,
+}
+
+impl LiveReload {
+    /// Sockets are closed when `shutdown` triggers, since they would
+    /// otherwise hold up draining until the timeout.
+    pub fn watch(static_dir: &Path, shutdown: &Shutdown) -> notify::Result<Self> {
+        let (changes, _) = broadcast::channel(64);
+        let sender = changes.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if event.kind.is_access() {
+                    return;
+                }
+                let css_only = event
+                    .paths
+                    .iter()
+                    .all(|path| path.extension().is_some_and(|ext| ext == "css"));
+                // no receivers just means no browser is connected
+                let _ = sender.send(if css_only { Change::Css } else { Change::Other });
+            })?;
+        watcher.watch(static_dir, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            changes,
+            shutdown: shutdown.clone(),
+            _watcher: Arc::new(watcher),
+        })
+    }
+}
+
+pub fn router(live: LiveReload) -> Router {
+    Router::new().route(PATH, get(upgrade)).with_state(live)
+}
+
+async fn upgrade(State(live): State<LiveReload>, ws: WebSocketUpgrade) -> impl IntoResponse {
+    let changes = live.changes.subscribe();
+    let shutdown = live.shutdown.triggered();
+    ws.on_upgrade(move |socket| async move {
+        tokio::select! {
+            _ = push_changes(socket, changes) => {},
+            // the page reloads once the restarted server accepts it again
+            _ = shutdown => {},
+        }
+    })
+}
+
+async fn push_changes(mut socket: WebSocket, mut changes: broadcast::Receiver<Change>) {
+    loop {
+        let mut change = tokio::select! {
+            change = changes.recv() => match change {
+                Ok(change) => change,
+                Err(broadcast::error::RecvError::Lagged(_)) => Change::Other,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // the client never sends anything, so this is the socket closing
+            _ = socket.recv() => return,
+        };
+
+        tokio::time::sleep(SETTLE).await;
+        loop {
+            match changes.try_recv() {
+                Ok(Change::Css) => {}
+                Ok(Change::Other) | Err(broadcast::error::TryRecvError::Lagged(_)) => {
+                    change = Change::Other
+                }
+                Err(_) => break,
+            }
+        }
+
+        let message = match change {
+            Change::Css => "css",
+            Change::Other => "reload",
+        };
+        if socket.send(Message::Text(message.into())).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{Config, Opt};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A websocket connected to `PATH` on `addr`, after the handshake.
+    async fn connect(addr: std::net::SocketAddr) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let handshake = format!(
+            "GET {PATH} HTTP/1.1\r\nHost: {addr}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+        );
+        stream.write_all(handshake.as_bytes()).await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+        stream
+    }
+
+    /// The next message from the server, a short unmasked text frame.
+    async fn next_message(stream: &mut TcpStream) -> String {
+        let read = async {
+            let mut frame = [0; 2];
+            stream.read_exact(&mut frame).await.unwrap();
+            assert_eq!(frame[0], 0x81, "not a single text frame");
+            let mut text = vec![0; usize::from(frame[1])];
+            stream.read_exact(&mut text).await.unwrap();
+            String::from_utf8(text).unwrap()
+        };
+        tokio::time::timeout(TIMEOUT, read)
+            .await
+            .expect("no message from the server")
+    }
+
+    #[tokio::test]
+    async fn changes_are_pushed() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = LiveReload::watch(dir.path(), &Shutdown::default()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(live)).await });
+
+        let mut socket = connect(addr).await;
+        std::fs::write(dir.path().join("style.css"), "body {}").unwrap();
+        assert_eq!(next_message(&mut socket).await, "css");
+        std::fs::write(dir.path().join("app.js"), "init()").unwrap();
+        std::fs::write(dir.path().join("style.css"), "body { margin: 0 }").unwrap();
+        assert_eq!(next_message(&mut socket).await, "reload");
+    }
+
+    async fn index(dev: bool) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("index.html"),
+            "<!DOCTYPE html><html><body></body></html>",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("server.toml"), "").unwrap();
+        let config = Config::load(Opt {
+            config: Some(dir.path().join("server.toml")),
+            static_dir: Some(dir.path().to_owned()),
+            content_dir: Some(dir.path().join("content")),
+            dev: Some(dev),
+            ..Opt::default()
+        })
+        .unwrap();
+        let app = crate::setup_app(&config, &Shutdown::default())
+            .await
+            .unwrap();
+        let res = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn script_only_with_dev() {
+        let with = index(true).await;
+        assert!(with.contains(PATH), "{with}");
+        assert!(with.ends_with("</script></body></html>"), "{with}");
+        let without = index(false).await;
+        assert!(!without.contains(PATH), "{without}");
+    }
+}
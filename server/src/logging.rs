@@ -0,0 +1,91 @@
+//! Logging setup shared by the server and vercel binaries.
+//!
+//! The filter can be changed while running, through [`filter`], without
+//! losing the state a restart would.
+
+use std::sync::{Mutex, OnceLock};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+use crate::Config;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// human readable lines
+    #[default]
+    Text,
+    /// one JSON object per line, with the fields of every enclosing span
+    Json,
+}
+
+static FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// The filter directive of the installed subscriber, like
+/// `info,server=debug`.
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// the filter reload handle only hands out the parsed filter
+    current: Mutex<String>,
+}
+
+impl LogFilter {
+    /// A filter at `directive`, which has to parse, for the returned layer.
+    pub(crate) fn new(directive: String) -> (reload::Layer<EnvFilter, Registry>, Self) {
+        let (layer, handle) = reload::Layer::new(EnvFilter::new(&directive));
+        let filter = Self {
+            handle,
+            current: Mutex::new(directive),
+        };
+        (layer, filter)
+    }
+
+    pub fn current(&self) -> String {
+        self.current.lock().expect("log filter poisoned").clone()
+    }
+
+    /// Replace the filter, keeping the old one if `directive` is invalid.
+    pub fn set(&self, directive: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directive).map_err(|err| err.to_string())?;
+        let mut current = self.current.lock().expect("log filter poisoned");
+        self.handle.reload(filter).map_err(|err| err.to_string())?;
+        *current = directive.to_owned();
+        Ok(())
+    }
+}
+
+/// The filter installed by [`init`], if it ran.
+pub fn filter() -> Option<&'static LogFilter> {
+    FILTER.get()
+}
+
+/// The directive for `config`: `RUST_LOG` if set, else `config.log_level`.
+pub fn directive(config: &Config) -> String {
+    std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directive| !directive.is_empty())
+        .unwrap_or_else(|| format!("{},hyper=info,mio=info", config.log_level))
+}
+
+/// Install the global subscriber, logging at [`directive`].
+pub fn init(config: &Config) {
+    let mut directive = directive(config);
+    if let Err(err) = EnvFilter::try_new(&directive) {
+        eprintln!("ignoring invalid log filter {directive:?}: {err}");
+        directive = "info".to_owned();
+    }
+    let (filter, log_filter) = LogFilter::new(directive);
+
+    let json = config.log_format == LogFormat::Json;
+    tracing_subscriber::registry()
+        .with(filter)
+        .with((!json).then(fmt::layer))
+        .with(json.then(|| fmt::layer().json()))
+        .init();
+
+    let _ = FILTER.set(log_filter);
+}
@@ -1,32 +1,158 @@
-use std::net::{IpAddr, Ipv6Addr, SocketAddr};
-use std::str::FromStr;
+use std::net::SocketAddr;
 
 use clap::Parser;
 
 #[tokio::main]
 async fn main() {
-    let opt = server::Opt::parse();
+    let mut opt = server::Opt::parse();
+    let command = opt.command.take();
+    let config = match server::Config::load(opt) {
+        Ok(config) => config,
+        Err(err) => {
+            // logging is configured from the config, so it is not set up yet
+            eprintln!("error: {err}");
+            std::process::exit(2);
+        }
+    };
 
-    // Setup logging & RUST_LOG from args
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", format!("{},hyper=info,mio=info", opt.log_level))
+    server::logging::init(&config);
+    #[cfg(unix)]
+    reload_log_filter_on_sighup();
+
+    if let Some(server::Command::Compress) = command {
+        let stats = match server::compress::compress_dir(&config.static_dir) {
+            Ok(stats) => stats,
+            Err(err) => {
+                log::error!("failed to compress {}: {err}", config.static_dir.display());
+                std::process::exit(1);
+            }
+        };
+        log::info!(
+            "compressed {}: {} written, {} up to date, {} not worth compressing",
+            config.static_dir.display(),
+            stats.written,
+            stats.up_to_date,
+            stats.skipped
+        );
+        return;
     }
 
-    // enable console logging
-    tracing_subscriber::fmt::init();
+    let routing = match &command {
+        Some(server::Command::EmulateVercel { vercel_json }) => {
+            match server::vercel::Routing::load(vercel_json) {
+                Ok(routing) => Some(routing),
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let shutdown = server::Shutdown::default();
+    shutdown.trigger_on_signal();
+
+    let app = match server::setup_app(&config, &shutdown).await {
+        Ok(app) => app,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let app = match routing {
+        Some(routing) => server::vercel::emulator(routing, app, &config),
+        None => app,
+    };
 
-    let app = server::setup_app(opt.static_dir).await;
+    let Some(tls) = &config.tls else {
+        log::info!("listening on http://{}", config.addr);
 
-    let sock_addr = SocketAddr::from((
-        IpAddr::from_str(opt.addr.as_str()).unwrap_or(IpAddr::V6(Ipv6Addr::LOCALHOST)),
-        opt.port,
-    ));
+        let listener = tokio::net::TcpListener::bind(config.addr).await.unwrap();
+
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+        let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.triggered());
+        if let Some(result) = shutdown.drain(server, config.shutdown_timeout).await {
+            result.expect("Unable to start server");
+        }
+        return;
+    };
+
+    let rustls = match server::tls::load(tls).await {
+        Ok(rustls) => rustls,
+        Err(err) => {
+            log::error!("failed to load certificate {}: {err}", tls.cert.display());
+            std::process::exit(1);
+        }
+    };
+    match server::tls::watch(rustls.clone(), tls) {
+        Ok(watcher) => shutdown.on_shutdown("certificate watcher", || async { drop(watcher) }),
+        Err(err) => log::warn!("not reloading certificate on change: {err}"),
+    }
 
-    log::info!("listening on http://{}", sock_addr);
+    if let Some(redirect_addr) = config.http_redirect {
+        log::info!("redirecting http://{redirect_addr} to https");
+        let listener = tokio::net::TcpListener::bind(redirect_addr).await.unwrap();
+        let redirect = server::tls::redirect_app(config.addr.port());
+        let redirect_shutdown = shutdown.triggered();
+        tokio::spawn(async move {
+            axum::serve(listener, redirect)
+                .with_graceful_shutdown(redirect_shutdown)
+                .await
+                .expect("Unable to start redirect server");
+        });
+    }
+
+    log::info!("listening on https://{}", config.addr);
+
+    let handle = axum_server::Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        let triggered = shutdown.triggered();
+        let timeout = config.shutdown_timeout;
+        async move {
+            triggered.await;
+            handle.graceful_shutdown(Some(timeout));
+        }
+    });
+    let server = axum_server::bind_rustls(config.addr, rustls)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    if let Some(result) = shutdown.drain(server, config.shutdown_timeout).await {
+        result.expect("Unable to start server");
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind(sock_addr).await.unwrap();
+/// Apply the log level from the config file again on SIGHUP, for changes
+/// made to it or to undo changes made through the admin endpoint.
+#[cfg(unix)]
+fn reload_log_filter_on_sighup() {
+    use tokio::signal::unix::{signal, SignalKind};
 
-    axum::serve(listener, app)
-        .await
-        .expect("Unable to start server");
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            log::warn!("not reloading the log filter on SIGHUP: {err}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            let config = match server::Config::load(server::Opt::parse()) {
+                Ok(config) => config,
+                Err(err) => {
+                    log::error!("keeping log filter: {err}");
+                    continue;
+                }
+            };
+            let Some(filter) = server::logging::filter() else {
+                return;
+            };
+            let directive = server::logging::directive(&config);
+            match filter.set(&directive) {
+                Ok(()) => log::info!("log filter set to {directive:?}"),
+                Err(err) => log::error!("keeping log filter: {err}"),
+            }
+        }
+    });
 }
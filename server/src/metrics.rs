@@ -0,0 +1,217 @@
+//! Request metrics in the Prometheus text format.
+//!
+//! Requests are labelled by the route pattern they matched, like
+//! `/api/posts/{slug}`, never by the raw path: everything the static files
+//! and the single page app fallback answer is the one route `fallback`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::body::HttpBody;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+
+use crate::auth;
+
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const SIZE_BUCKETS: [f64; 6] = [1e2, 1e3, 1e4, 1e5, 1e6, 1e7];
+
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    /// where the metrics are served, starting with `/`
+    pub path: String,
+    /// required as `Authorization: Bearer <token>` when set
+    pub token: Option<String>,
+}
+
+/// The recorded metrics, shared by the recording middleware and the endpoint.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    series: Arc<Mutex<BTreeMap<Labels, Series>>>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    method: &'static str,
+    route: String,
+    status: u16,
+}
+
+struct Series {
+    requests: u64,
+    duration: Histogram<{ DURATION_BUCKETS.len() }>,
+    size: Histogram<{ SIZE_BUCKETS.len() }>,
+}
+
+struct Histogram<const N: usize> {
+    /// not cumulative, summed up when rendered
+    buckets: [u64; N],
+    count: u64,
+    sum: f64,
+}
+
+impl<const N: usize> Histogram<N> {
+    fn new() -> Self {
+        Self {
+            buckets: [0; N],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, bounds: &[f64; N], value: f64) {
+        if let Some(bucket) = bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str, bounds: &[f64; N]) {
+        let mut cumulative = 0;
+        for (bound, count) in bounds.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+impl Metrics {
+    fn observe(&self, labels: Labels, seconds: f64, size: Option<u64>) {
+        let mut series = self.series.lock().expect("metrics lock poisoned");
+        let series = series.entry(labels).or_insert_with(|| Series {
+            requests: 0,
+            duration: Histogram::new(),
+            size: Histogram::new(),
+        });
+        series.requests += 1;
+        series.duration.observe(&DURATION_BUCKETS, seconds);
+        if let Some(size) = size {
+            series.size.observe(&SIZE_BUCKETS, size as f64);
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let series = self.series.lock().expect("metrics lock poisoned");
+        let labelled: Vec<_> = series
+            .iter()
+            .map(|(labels, series)| {
+                let labels = format!(
+                    "method=\"{}\",route=\"{}\",status=\"{}\"",
+                    labels.method,
+                    escape_label(&labels.route),
+                    labels.status
+                );
+                (labels, series)
+            })
+            .collect();
+
+        let mut out = String::new();
+        out.push_str("# HELP http_requests_total Requests answered.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for (labels, series) in &labelled {
+            let _ = writeln!(out, "http_requests_total{{{labels}}} {}", series.requests);
+        }
+
+        let name = "http_request_duration_seconds";
+        out.push_str("# HELP http_request_duration_seconds Time until the response head.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (labels, series) in &labelled {
+            series
+                .duration
+                .render(&mut out, name, labels, &DURATION_BUCKETS);
+        }
+
+        let name = "http_response_size_bytes";
+        out.push_str("# HELP http_response_size_bytes Size of response bodies of known length.\n");
+        out.push_str("# TYPE http_response_size_bytes histogram\n");
+        for (labels, series) in &labelled {
+            series.size.render(&mut out, name, labels, &SIZE_BUCKETS);
+        }
+        out
+    }
+}
+
+/// Middleware recording every request. Must be added with `Router::layer`,
+/// since the matched route is only known inside the router.
+pub async fn record(State(metrics): State<Metrics>, req: Request, next: Next) -> Response {
+    let method = method_label(req.method());
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "fallback".to_owned());
+    let start = Instant::now();
+
+    let res = next.run(req).await;
+
+    let labels = Labels {
+        method,
+        route,
+        status: res.status().as_u16(),
+    };
+    // streamed bodies of unknown length are counted but not sized
+    let size = res.body().size_hint().exact();
+    metrics.observe(labels, start.elapsed().as_secs_f64(), size);
+    res
+}
+
+pub fn router(metrics: Metrics, config: &MetricsConfig) -> Router {
+    let token: Option<Arc<str>> = config.token.as_deref().map(Into::into);
+    Router::new()
+        .route(&config.path, get(serve))
+        .with_state((metrics, token))
+}
+
+async fn serve(
+    State((metrics, token)): State<(Metrics, Option<Arc<str>>)>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(token) = token {
+        if !auth::has_bearer(&headers, &token) {
+            return auth::unauthorized();
+        }
+    }
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        metrics.render(),
+    )
+        .into_response()
+}
+
+/// Arbitrary methods would each get their own series.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::OPTIONS => "OPTIONS",
+        _ => "other",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
@@ -0,0 +1,224 @@
+//! The OpenAPI 3.1 document of the `/api` routes at `/api/openapi.json`, and
+//! a page showing it to people at `/api/docs`.
+//!
+//! Every `/api` route is registered on an [`OpenApiRouter`] with `routes!`,
+//! which only takes handlers with a `#[utoipa::path]`, so a route cannot be
+//! added without being documented. `OpenApiRouter::route` would get around
+//! that, so `clippy.toml` disallows it, and `api_routes_are_documented` in
+//! `tests/app.rs` checks the routes of the whole app against the document.
+//!
+//! The page is rendered once from the document, without scripts, so it works
+//! under the Content-Security-Policy of the site.
+
+use std::fmt::Write;
+
+use axum::http::header;
+use axum::response::{Html, IntoResponse};
+use axum::Extension;
+use bytes::Bytes;
+use serde_json::Value;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use crate::feed::escape_xml;
+
+/// What the document says before the routes add themselves.
+#[derive(OpenApi)]
+#[openapi(
+    info(description = "The JSON API of the site, used by its frontend."),
+    modifiers(&AdminToken),
+    tags(
+        (name = "posts", description = "Posts from the content directory"),
+        (name = "contact", description = "The contact form"),
+        (name = "status", description = "Uptime checks and the deployed build"),
+        (name = "admin", description = "Operating a running server, behind `--admin-token`"),
+        (name = "meta", description = "This document, CSP reports and the hello demo"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The `admin_token` security scheme of the admin routes.
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// The document as served, rendered once when the app is set up and handed
+/// to the routes as an [`Extension`].
+#[derive(Clone)]
+pub struct Rendered {
+    json: Bytes,
+    html: Bytes,
+}
+
+impl Rendered {
+    pub fn new(openapi: &utoipa::openapi::OpenApi) -> Self {
+        let value = serde_json::to_value(openapi).expect("OpenAPI document is not JSON");
+        Self {
+            json: serde_json::to_vec(&value)
+                .expect("OpenAPI document is not JSON")
+                .into(),
+            html: render_html(&value).into(),
+        }
+    }
+}
+
+/// `/api/openapi.json`, and `/api/docs` if `docs`. Needs a [`Rendered`]
+/// layered on once all routes are known.
+pub fn router(docs: bool) -> OpenApiRouter {
+    let router = OpenApiRouter::new().routes(routes!(openapi_json));
+    if docs {
+        router.routes(routes!(docs_page))
+    } else {
+        router
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "meta",
+    responses((status = OK, description = "This document", content_type = "application/json"))
+)]
+async fn openapi_json(Extension(rendered): Extension<Rendered>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], rendered.json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/docs",
+    tag = "meta",
+    responses((status = OK, description = "This document as a page", content_type = "text/html"))
+)]
+async fn docs_page(Extension(rendered): Extension<Rendered>) -> impl IntoResponse {
+    Html(rendered.html)
+}
+
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Renders the serialized document, which is easier to walk than the typed
+/// one and shows exactly what `/api/openapi.json` says.
+fn render_html(doc: &Value) -> String {
+    let text = |value: &Value| value.as_str().map(escape_xml).unwrap_or_default();
+    let title = text(&doc["info"]["title"]);
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title} API</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 56rem; margin: 2rem auto; padding: 0 1rem; }}\n\
+         code, pre {{ background: #f4f4f4; }}\n\
+         pre {{ padding: 0.5rem; overflow-x: auto; }}\n\
+         .method {{ font-weight: bold; text-transform: uppercase; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title} {}</h1>\n<p>{}</p>\n\
+         <p>The same as <a href=\"/api/openapi.json\">OpenAPI</a>.</p>\n",
+        text(&doc["info"]["version"]),
+        text(&doc["info"]["description"]),
+    );
+
+    let empty = serde_json::Map::new();
+    for (path, item) in doc["paths"].as_object().unwrap_or(&empty) {
+        for method in METHODS {
+            let Some(op) = item.get(method) else {
+                continue;
+            };
+            let _ = writeln!(
+                html,
+                "<h2><span class=\"method\">{method}</span> <code>{}</code></h2>",
+                escape_xml(path)
+            );
+            for key in ["summary", "description"] {
+                if let Some(value) = op[key].as_str() {
+                    let _ = writeln!(html, "<p>{}</p>", escape_xml(value));
+                }
+            }
+            if let Some(security) = op["security"].as_array() {
+                let schemes: Vec<_> = security
+                    .iter()
+                    .filter_map(Value::as_object)
+                    .flat_map(|req| req.keys())
+                    .map(|name| format!("<code>{}</code>", escape_xml(name)))
+                    .collect();
+                let _ = writeln!(html, "<p>Needs {}.</p>", schemes.join(" or "));
+            }
+            if let Some(params) = op["parameters"].as_array() {
+                html.push_str("<h3>Parameters</h3>\n<ul>\n");
+                for param in params {
+                    let _ = writeln!(
+                        html,
+                        "<li><code>{}</code> in {}{} {}</li>",
+                        text(&param["name"]),
+                        text(&param["in"]),
+                        if param["required"] == true { ", required" } else { "" },
+                        text(&param["description"]),
+                    );
+                }
+                html.push_str("</ul>\n");
+            }
+            if let Some(body) = op.get("requestBody") {
+                html.push_str("<h3>Request body</h3>\n<ul>\n");
+                render_content(&mut html, &body["content"]);
+                html.push_str("</ul>\n");
+            }
+            html.push_str("<h3>Responses</h3>\n<ul>\n");
+            for (status, res) in op["responses"].as_object().unwrap_or(&empty) {
+                let _ = writeln!(
+                    html,
+                    "<li><strong>{}</strong> {}<ul>",
+                    escape_xml(status),
+                    text(&res["description"])
+                );
+                render_content(&mut html, &res["content"]);
+                html.push_str("</ul></li>\n");
+            }
+            html.push_str("</ul>\n");
+        }
+    }
+
+    html.push_str("<h2>Schemas</h2>\n");
+    for (name, schema) in doc["components"]["schemas"].as_object().unwrap_or(&empty) {
+        let pretty = serde_json::to_string_pretty(schema).unwrap_or_default();
+        let _ = write!(
+            html,
+            "<h3 id=\"schema-{name}\">{name}</h3>\n<pre>{}</pre>\n",
+            escape_xml(&pretty),
+            name = escape_xml(name),
+        );
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// A list item per content type, linking to the schema when it is a `$ref`.
+fn render_content(html: &mut String, content: &Value) {
+    let Some(content) = content.as_object() else {
+        return;
+    };
+    for (content_type, media) in content {
+        let schema = &media["schema"];
+        let schema = match schema["$ref"].as_str() {
+            Some(reference) => {
+                let name = escape_xml(reference.rsplit('/').next().unwrap_or(reference));
+                format!("<a href=\"#schema-{name}\">{name}</a>")
+            }
+            None if schema.is_null() => String::new(),
+            None => format!("<code>{}</code>", escape_xml(&schema.to_string())),
+        };
+        let _ = writeln!(
+            html,
+            "<li><code>{}</code> {schema}</li>",
+            escape_xml(content_type)
+        );
+    }
+}
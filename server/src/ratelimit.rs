@@ -0,0 +1,432 @@
+//! Per client rate limiting with token buckets.
+//!
+//! Every rule covers the requests under a path prefix, and every client gets
+//! its own bucket per rule: up to `burst` requests at once, refilled at
+//! `per_second`. The longest matching prefix wins. Clients over the limit get
+//! a 429 with `Retry-After`.
+//!
+//! Clients are told apart by IP. `X-Forwarded-For` is only believed when the
+//! connection comes from a trusted proxy, or when there is no connection to
+//! look at, as on vercel, whose edge overwrites the header.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+/// Past this many clients per rule, buckets that are full again are
+/// forgotten, since a new bucket starts out full anyway.
+const MAX_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitRule {
+    /// path prefix, matched by whole segments
+    pub prefix: String,
+    /// requests a client can make at once
+    pub burst: u32,
+    /// rate at which used up requests come back
+    pub per_second: f64,
+}
+
+impl RateLimitRule {
+    /// The rules used unless the config file has its own.
+    pub fn default_rules() -> Vec<RateLimitRule> {
+        vec![
+            RateLimitRule {
+                prefix: "/api".to_owned(),
+                burst: 100,
+                per_second: 20.0,
+            },
+            RateLimitRule {
+                prefix: "/api/contact".to_owned(),
+                burst: 3,
+                per_second: 1.0 / 60.0,
+            },
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.prefix.starts_with('/') {
+            return Err("prefix must start with /".to_owned());
+        }
+        if self.burst == 0 {
+            return Err("burst must be at least 1".to_owned());
+        }
+        if !(self.per_second.is_finite() && self.per_second > 0.0) {
+            return Err("per_second must be positive".to_owned());
+        }
+        Ok(())
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let prefix = self.prefix.trim_end_matches('/');
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+/// An address or a CIDR range, like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                masked(net.to_bits().into(), self.prefix_len, 32)
+                    == masked(ip.to_bits().into(), self.prefix_len, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                masked(net.to_bits(), self.prefix_len, 128)
+                    == masked(ip.to_bits(), self.prefix_len, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn masked(bits: u128, prefix_len: u8, width: u8) -> u128 {
+    match width - prefix_len {
+        0 => bits,
+        host if host >= 128 => 0,
+        host => bits >> host,
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|err| format!("{err}"))?;
+        let addr = addr.to_canonical();
+        let width = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            None => width,
+            Some(len) => len
+                .parse()
+                .ok()
+                .filter(|len| *len <= width)
+                .ok_or_else(|| format!("prefix length must be 0 to {width}"))?,
+        };
+        Ok(IpNet { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, rule: &RateLimitRule, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rule.per_second).min(rule.burst as f64);
+        self.updated = now;
+    }
+}
+
+/// The buckets of every client for one rule.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rule: RateLimitRule,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rule: RateLimitRule) -> Self {
+        Self {
+            rule,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a request from the bucket of `client` at time `now`, or tell
+    /// how long until the next one is available.
+    pub fn try_acquire(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let rule = &self.rule;
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(&client) {
+            buckets.retain(|_, bucket| {
+                bucket.refill(rule, now);
+                bucket.tokens < rule.burst as f64
+            });
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: rule.burst as f64,
+            updated: now,
+        });
+        bucket.refill(rule, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / rule.per_second,
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimits {
+    /// longest prefix first
+    limiters: Vec<RateLimiter>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl RateLimits {
+    pub fn new(rules: &[RateLimitRule], trusted_proxies: &[IpNet]) -> Self {
+        let mut limiters: Vec<_> = rules.iter().cloned().map(RateLimiter::new).collect();
+        limiters.sort_by_key(|limiter| std::cmp::Reverse(limiter.rule.prefix.len()));
+        Self {
+            limiters,
+            trusted_proxies: trusted_proxies.to_vec(),
+        }
+    }
+
+    /// The address of the client, `peer` being the address of the
+    /// connection when there is one.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let trusted = |ip: IpAddr| self.trusted_proxies.iter().any(|net| net.contains(ip));
+        if let Some(peer) = peer.filter(|peer| !trusted(*peer)) {
+            return Some(peer);
+        }
+
+        // proxies append the address they saw, so the rightmost address
+        // that is not a trusted proxy is the client. Anything left of it was
+        // written by the client, and an entry that does not parse (e.g. an
+        // `unknown` from a proxy) ends what can be told, leaving the last
+        // proxy that could be seen.
+        let forwarded: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        let mut client = peer;
+        for entry in forwarded.iter().rev() {
+            let Ok(ip) = entry.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = Some(ip);
+            if !trusted(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+pub async fn limit(State(limits): State<Arc<RateLimits>>, req: Request, next: Next) -> Response {
+    let Some(limiter) = limits
+        .limiters
+        .iter()
+        .find(|limiter| limiter.rule.matches(req.uri().path()))
+    else {
+        return next.run(req).await;
+    };
+
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let Some(client) = limits.client_ip(peer, req.headers()) else {
+        // nothing to tell clients apart by
+        return next.run(req).await;
+    };
+
+    match limiter.try_acquire(client.to_canonical(), Instant::now()) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => {
+            log::debug!("rate limited {client} on {}", limiter.rule.prefix);
+            let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            // the body is left to `error::pages`, which knows who is asking
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds.to_string())],
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn limiter(burst: u32, per_second: f64) -> RateLimiter {
+        RateLimiter::new(RateLimitRule {
+            prefix: "/api".to_owned(),
+            burst,
+            per_second,
+        })
+    }
+
+    #[test]
+    fn burst_then_limited() {
+        let limiter = limiter(3, 0.5);
+        let client = ip("192.0.2.1");
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire(client, now), Ok(()));
+        }
+        assert_eq!(
+            limiter.try_acquire(client, now),
+            Err(Duration::from_secs(2))
+        );
+        // other clients have their own bucket
+        assert_eq!(limiter.try_acquire(ip("192.0.2.2"), now), Ok(()));
+    }
+
+    #[test]
+    fn refill() {
+        let limiter = limiter(2, 2.0);
+        let client = ip("192.0.2.1");
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        assert_eq!(limiter.try_acquire(client, at(0)), Ok(()));
+        assert_eq!(limiter.try_acquire(client, at(0)), Ok(()));
+        assert_eq!(
+            limiter.try_acquire(client, at(250)),
+            Err(Duration::from_millis(250))
+        );
+        assert_eq!(limiter.try_acquire(client, at(500)), Ok(()));
+        assert!(limiter.try_acquire(client, at(500)).is_err());
+
+        // refilling stops at the burst
+        let later = at(60_000);
+        assert_eq!(limiter.try_acquire(client, later), Ok(()));
+        assert_eq!(limiter.try_acquire(client, later), Ok(()));
+        assert!(limiter.try_acquire(client, later).is_err());
+    }
+
+    #[test]
+    fn ipnet_v4() {
+        let ten = net("10.0.0.0/8");
+        assert!(ten.contains(ip("10.255.1.2")));
+        assert!(!ten.contains(ip("11.0.0.1")));
+        assert!(net("192.0.2.7").contains(ip("192.0.2.7")));
+        assert!(!net("192.0.2.7").contains(ip("192.0.2.8")));
+        assert!(net("0.0.0.0/0").contains(ip("203.0.113.9")));
+        assert!(!ten.contains(ip("::1")));
+    }
+
+    #[test]
+    fn ipnet_v6() {
+        let doc = net("2001:db8::/32");
+        assert!(doc.contains(ip("2001:db8:1::1")));
+        assert!(!doc.contains(ip("2001:db9::1")));
+        assert!(net("::1").contains(ip("::1")));
+        assert!(net("::/0").contains(ip("fe80::1")));
+        assert!(!net("::/0").contains(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn ipnet_v4_mapped() {
+        // mapped addresses are the v4 address, whichever side they are on
+        assert!(net("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+        assert!(!net("10.0.0.0/8").contains(ip("::ffff:11.1.2.3")));
+        assert_eq!(net("::ffff:10.0.0.0/8"), net("10.0.0.0/8"));
+        assert!(net("::ffff:127.0.0.1").contains(ip("127.0.0.1")));
+    }
+
+    #[test]
+    fn ipnet_parse_errors() {
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("::/129".parse::<IpNet>().is_err());
+        assert!("10.0.0.0/x".parse::<IpNet>().is_err());
+        assert!("localhost".parse::<IpNet>().is_err());
+        assert_eq!(net("10.0.0.0/8").to_string(), "10.0.0.0/8");
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn client_ip_from_untrusted_peer() {
+        let limits = RateLimits::new(&[], &[net("10.0.0.0/8")]);
+        let headers = forwarded(&["198.51.100.1"]);
+        assert_eq!(
+            limits.client_ip(Some(ip("192.0.2.1")), &headers),
+            Some(ip("192.0.2.1"))
+        );
+    }
+
+    #[test]
+    fn client_ip_skips_trusted_proxies_from_the_right() {
+        let limits = RateLimits::new(&[], &[net("10.0.0.0/8")]);
+        let proxy = Some(ip("10.0.0.1"));
+        let headers = forwarded(&["198.51.100.1, 192.0.2.1, 10.0.0.2"]);
+        assert_eq!(limits.client_ip(proxy, &headers), Some(ip("192.0.2.1")));
+        // across header lines too
+        let headers = forwarded(&["198.51.100.1", "192.0.2.1", "10.0.0.2"]);
+        assert_eq!(limits.client_ip(proxy, &headers), Some(ip("192.0.2.1")));
+        // only proxies, so the one furthest out
+        let headers = forwarded(&["10.0.0.3, 10.0.0.2"]);
+        assert_eq!(limits.client_ip(proxy, &headers), Some(ip("10.0.0.3")));
+        // no header
+        assert_eq!(limits.client_ip(proxy, &HeaderMap::new()), proxy);
+    }
+
+    #[test]
+    fn client_ip_stops_at_unparseable() {
+        let limits = RateLimits::new(&[], &[net("10.0.0.0/8")]);
+        let proxy = Some(ip("10.0.0.1"));
+        // whatever the client wrote on the left is of no consequence
+        let headers = forwarded(&["not an ip, 192.0.2.1, 10.0.0.2"]);
+        assert_eq!(limits.client_ip(proxy, &headers), Some(ip("192.0.2.1")));
+        // an upstream proxy that did not know, so the last proxy seen
+        let headers = forwarded(&["192.0.2.1, unknown, 10.0.0.2"]);
+        assert_eq!(limits.client_ip(proxy, &headers), Some(ip("10.0.0.2")));
+        let headers = forwarded(&["unknown"]);
+        assert_eq!(limits.client_ip(proxy, &headers), proxy);
+    }
+
+    #[test]
+    fn client_ip_without_peer() {
+        // as on vercel, where the edge writes the header
+        let limits = RateLimits::new(&[], &[]);
+        let headers = forwarded(&["198.51.100.1, 192.0.2.1"]);
+        assert_eq!(limits.client_ip(None, &headers), Some(ip("192.0.2.1")));
+        assert_eq!(limits.client_ip(None, &HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,55 @@
+//! `X-Request-Id` on every request and response, and in the request span so
+//! every log line of a request carries it.
+//!
+//! `SetRequestIdLayer` keeps an id sent by a client or proxy and makes one
+//! up otherwise, `PropagateRequestIdLayer` echoes it in the response. Ids
+//! that would not make for a sane log field are dropped by [`drop_invalid`]
+//! first, so they get replaced as well.
+
+use axum::body::Body;
+use axum::http::{HeaderName, HeaderValue, Request};
+use tower_http::request_id::RequestId;
+use tracing::Span;
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longer incoming ids are replaced.
+const MAX_LEN: usize = 128;
+
+/// For `map_request`, outside of `SetRequestIdLayer`: removes every incoming
+/// id unless there is exactly one and it is [`is_valid`].
+pub async fn drop_invalid(mut req: Request<Body>) -> Request<Body> {
+    let mut ids = req.headers().get_all(&HEADER).iter();
+    let keep = matches!((ids.next(), ids.next()), (Some(id), None) if is_valid(id));
+    if !keep {
+        req.headers_mut().remove(&HEADER);
+    }
+    req
+}
+
+/// Up to [`MAX_LEN`] ASCII letters, digits and `-_.:+/=`, which covers
+/// UUIDs and the ids of the usual proxies and load balancers.
+fn is_valid(id: &HeaderValue) -> bool {
+    let id = id.as_bytes();
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:+/=".contains(b))
+}
+
+/// The span for `TraceLayer`, which needs to sit inside `SetRequestIdLayer`.
+pub fn make_span(req: &Request<Body>) -> Span {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    tracing::debug_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        request_id,
+    )
+}
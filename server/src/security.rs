@@ -0,0 +1,223 @@
+//! Security headers on every response.
+//!
+//! The default Content-Security-Policy fits trunk's output: the wasm module
+//! needs `'wasm-unsafe-eval'`, and the inline bootstrap script in
+//! `index.html` gets a fresh nonce on every request (see [`CspNonce`]).
+//! Violations are reported to `/api/csp-report`, which logs them.
+//!
+//! Every header can be changed or turned off with an empty string in the
+//! `[security_headers]` table of the config file.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Deserialize;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use crate::error::ApiError;
+
+/// `{nonce}` is replaced by the nonce of the request.
+pub const DEFAULT_CSP: &str = "default-src 'self'; \
+    script-src 'self' 'wasm-unsafe-eval' 'nonce-{nonce}'; \
+    style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data:; \
+    object-src 'none'; \
+    base-uri 'self'; \
+    frame-ancestors 'none'; \
+    report-uri /api/csp-report";
+
+/// The `[security_headers]` table of the config file, every header optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityHeadersOpt {
+    /// [default: DEFAULT_CSP]
+    pub content_security_policy: Option<String>,
+    /// send the policy as Content-Security-Policy-Report-Only, to try it out
+    pub csp_report_only: Option<bool>,
+    /// [default: max-age=63072000; includeSubDomains]
+    pub strict_transport_security: Option<String>,
+    /// [default: nosniff]
+    pub content_type_options: Option<String>,
+    /// [default: strict-origin-when-cross-origin]
+    pub referrer_policy: Option<String>,
+    /// send COOP and COEP so the frontend can use SharedArrayBuffer, at the
+    /// cost of every cross origin resource needing CORP or CORS
+    pub cross_origin_isolation: Option<bool>,
+}
+
+/// The validated headers, shared with the middleware.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    /// with `{nonce}` still in it, `None` if there is no CSP
+    csp: Option<String>,
+    csp_header: HeaderName,
+    fixed: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeaders {
+    /// `Err` names the offending key.
+    pub fn new(opt: SecurityHeadersOpt) -> Result<Self, (&'static str, String)> {
+        let csp = opt
+            .content_security_policy
+            .unwrap_or_else(|| DEFAULT_CSP.to_owned());
+        if HeaderValue::from_str(&csp).is_err() {
+            return Err(("content_security_policy", csp));
+        }
+        let csp = (!csp.is_empty()).then_some(csp);
+        let csp_header = if opt.csp_report_only.unwrap_or(false) {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        };
+
+        let mut fixed = Vec::new();
+        let configurable = [
+            (
+                "strict_transport_security",
+                header::STRICT_TRANSPORT_SECURITY,
+                opt.strict_transport_security,
+                "max-age=63072000; includeSubDomains",
+            ),
+            (
+                "content_type_options",
+                header::X_CONTENT_TYPE_OPTIONS,
+                opt.content_type_options,
+                "nosniff",
+            ),
+            (
+                "referrer_policy",
+                header::REFERRER_POLICY,
+                opt.referrer_policy,
+                "strict-origin-when-cross-origin",
+            ),
+        ];
+        for (key, name, value, default) in configurable {
+            let value = value.unwrap_or_else(|| default.to_owned());
+            if value.is_empty() {
+                continue;
+            }
+            let value = HeaderValue::from_str(&value).map_err(|_| (key, value))?;
+            fixed.push((name, value));
+        }
+        if opt.cross_origin_isolation.unwrap_or(false) {
+            fixed.push((
+                HeaderName::from_static("cross-origin-opener-policy"),
+                HeaderValue::from_static("same-origin"),
+            ));
+            fixed.push((
+                HeaderName::from_static("cross-origin-embedder-policy"),
+                HeaderValue::from_static("require-corp"),
+            ));
+        }
+
+        Ok(Self {
+            csp,
+            csp_header,
+            fixed,
+        })
+    }
+}
+
+/// The nonce of the current request, for the `<script>` elements of
+/// `index.html`. Only set when the policy has one.
+#[derive(Debug, Clone)]
+pub struct CspNonce(pub Arc<str>);
+
+impl CspNonce {
+    fn generate() -> Self {
+        let mut bytes = [0; 16];
+        getrandom::fill(&mut bytes).expect("no randomness for the CSP nonce");
+        let mut nonce = String::with_capacity(32);
+        for byte in bytes {
+            let _ = write!(nonce, "{byte:02x}");
+        }
+        Self(nonce.into())
+    }
+}
+
+/// Middleware setting the headers, to be layered over the fallback too.
+pub async fn apply(
+    State(headers): State<Arc<SecurityHeaders>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let csp = headers.csp.as_ref().map(|csp| {
+        if !csp.contains("{nonce}") {
+            return csp.clone();
+        }
+        let nonce = CspNonce::generate();
+        let csp = csp.replace("{nonce}", &nonce.0);
+        req.extensions_mut().insert(nonce);
+        csp
+    });
+
+    let mut res = next.run(req).await;
+    let res_headers = res.headers_mut();
+    if let Some(csp) = csp.and_then(|csp| HeaderValue::from_str(&csp).ok()) {
+        res_headers.entry(headers.csp_header.clone()).or_insert(csp);
+    }
+    for (name, value) in &headers.fixed {
+        res_headers.entry(name.clone()).or_insert(value.clone());
+    }
+    res
+}
+
+pub fn router() -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(report))
+        .layer(DefaultBodyLimit::max(64 * 1024))
+}
+
+/// Takes both the old `application/csp-report` body with one report and the
+/// Reporting API's `application/reports+json` list of them.
+#[utoipa::path(
+    post,
+    path = "/api/csp-report",
+    tag = "meta",
+    request_body(content(
+        ("application/csp-report"),
+        ("application/reports+json"),
+    )),
+    responses(
+        (status = NO_CONTENT, description = "The violations were logged"),
+        (status = BAD_REQUEST, description = "The body is not JSON", body = shared::api::Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn report(body: String) -> Result<StatusCode, ApiError> {
+    let Ok(body) = serde_json::from_str::<serde_json::Value>(&body) else {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST).detail("The report is not JSON."));
+    };
+    let reports: Vec<serde_json::Value> = match body {
+        serde_json::Value::Array(reports) => reports
+            .into_iter()
+            .filter_map(|mut report| report.get_mut("body").map(serde_json::Value::take))
+            .collect(),
+        mut report => report
+            .get_mut("csp-report")
+            .map(serde_json::Value::take)
+            .into_iter()
+            .collect(),
+    };
+    for report in reports {
+        let field = |old: &str, new: &str| {
+            report
+                .get(old)
+                .or_else(|| report.get(new))
+                .and_then(|value| value.as_str())
+                .unwrap_or("?")
+                .to_owned()
+        };
+        log::warn!(
+            "csp violation of {} by {} on {}",
+            field("violated-directive", "effectiveDirective"),
+            field("blocked-uri", "blockedURL"),
+            field("document-uri", "documentURL"),
+        );
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,149 @@
+//! Graceful shutdown on SIGINT or SIGTERM.
+//!
+//! Once triggered, the listeners stop accepting and in-flight requests get
+//! a drain timeout to finish. Then the registered hooks run, so background
+//! tasks can flush whatever they hold before the process exits.
+
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+type Hook = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// A handle to the shutdown of the server, cheap to clone.
+///
+/// The vercel binary never triggers it, since the runtime ends the process.
+#[derive(Clone)]
+pub struct Shutdown {
+    triggered: Arc<watch::Sender<bool>>,
+    hooks: Arc<Mutex<Vec<(&'static str, Hook)>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            triggered: Arc::new(watch::Sender::new(false)),
+            hooks: Default::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Shutdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shutdown")
+            .field("triggered", &self.is_triggered())
+            .finish()
+    }
+}
+
+impl Shutdown {
+    /// Trigger on the first SIGINT or SIGTERM. Must be called within a tokio
+    /// runtime.
+    pub fn trigger_on_signal(&self) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            signal().await;
+            this.trigger();
+        });
+    }
+
+    pub fn trigger(&self) {
+        self.triggered.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Resolves once shutdown is triggered, immediately if it already was.
+    pub fn triggered(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut rx = self.triggered.subscribe();
+        async move {
+            // the sender lives in `self`, which every clone keeps alive, so
+            // an error means the whole server is gone anyway
+            let _ = rx.wait_for(|triggered| *triggered).await;
+        }
+    }
+
+    /// Run `hook` after the server has drained. `name` is only for the log.
+    pub fn on_shutdown<F, Fut>(&self, name: &'static str, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let hook: Hook = Box::new(move || Box::pin(hook()));
+        self.hooks
+            .lock()
+            .expect("shutdown hooks poisoned")
+            .push((name, hook));
+    }
+
+    /// Run `server` to completion, but give up on it `timeout` after
+    /// shutdown is triggered. Either way the hooks run before returning.
+    ///
+    /// `server` should stop accepting connections when [`triggered`]
+    /// resolves, e.g. through `with_graceful_shutdown`.
+    ///
+    /// [`triggered`]: Shutdown::triggered
+    pub async fn drain<F: IntoFuture>(&self, server: F, timeout: Duration) -> Option<F::Output> {
+        let deadline = {
+            let triggered = self.triggered();
+            async move {
+                triggered.await;
+                log::info!("shutting down, draining for up to {timeout:?}");
+                tokio::time::sleep(timeout).await;
+            }
+        };
+        let output = tokio::select! {
+            output = server.into_future() => Some(output),
+            _ = deadline => {
+                log::warn!("dropping connections still open after {timeout:?}");
+                None
+            }
+        };
+        self.run_hooks().await;
+        output
+    }
+
+    async fn run_hooks(&self) {
+        let hooks = std::mem::take(&mut *self.hooks.lock().expect("shutdown hooks poisoned"));
+        // registered last, set up last, so torn down first
+        for (name, hook) in hooks.into_iter().rev() {
+            log::debug!("running shutdown hook {name}");
+            hook().await;
+        }
+    }
+}
+
+async fn signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            log::error!("failed to listen for SIGINT: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                log::error!("failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,104 @@
+//! `/sitemap.xml` and `/robots.txt` for search engines.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, NaiveDate, Utc};
+use shared::routes::Route;
+
+use crate::content::PostStore;
+use crate::feed::escape_xml;
+
+#[derive(Clone)]
+struct SitemapState {
+    posts: Arc<PostStore>,
+    /// the paths of the pages without placeholders that this server has
+    pages: Arc<[String]>,
+    /// absolute site url without a trailing slash
+    base_url: String,
+    /// when the frontend was last built, if `index.html` could be found
+    built: Option<NaiveDate>,
+    robots: Arc<str>,
+}
+
+/// `robots` replaces the generated `/robots.txt`, which allows everything and
+/// points at the sitemap. `/contact` is only listed with a `contact_form`.
+pub fn router(
+    posts: Arc<PostStore>,
+    base_url: &str,
+    static_dir: &Path,
+    robots: Option<String>,
+    contact_form: bool,
+) -> Router {
+    let base_url = base_url.trim_end_matches('/').to_owned();
+    let built = std::fs::metadata(static_dir.join("index.html"))
+        .and_then(|meta| meta.modified())
+        .ok()
+        .map(|modified| DateTime::<Utc>::from(modified).date_naive());
+    let robots = robots
+        .unwrap_or_else(|| format!("User-agent: *\nAllow: /\n\nSitemap: {base_url}/sitemap.xml\n"));
+
+    let contact = Route::Contact.path();
+    let pages = Route::static_paths()
+        .filter(|path| contact_form || *path != contact)
+        .map(str::to_owned)
+        .collect();
+
+    let state = SitemapState {
+        posts,
+        pages,
+        base_url,
+        built,
+        robots: robots.into(),
+    };
+    Router::new()
+        .route("/sitemap.xml", get(sitemap))
+        .route("/robots.txt", get(robots_txt))
+        .with_state(state)
+}
+
+async fn sitemap(State(state): State<SitemapState>) -> impl IntoResponse {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#);
+
+    let pages = state
+        .pages
+        .iter()
+        .map(|path| (path.clone(), state.built))
+        .chain(state.posts.posts().iter().map(|post| {
+            let route = Route::Post {
+                slug: post.meta.slug.clone(),
+            };
+            (route.path(), Some(post.meta.date))
+        }));
+    for (path, lastmod) in pages {
+        let _ = write!(
+            xml,
+            "<url><loc>{}</loc>",
+            escape_xml(&format!("{}{path}", state.base_url))
+        );
+        if let Some(lastmod) = lastmod {
+            let _ = write!(xml, "<lastmod>{lastmod}</lastmod>");
+        }
+        xml.push_str("</url>");
+    }
+    xml.push_str("</urlset>");
+
+    (
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        xml,
+    )
+}
+
+async fn robots_txt(State(state): State<SitemapState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        state.robots.to_string(),
+    )
+}
@@ -0,0 +1,447 @@
+//! Static files with a single page app fallback.
+//!
+//! Paths that are not files are routes of the frontend, so browser navigations
+//! to them get `index.html` and the router in the wasm app takes over. Anything
+//! else that is missing, like a stale `.js` or `.wasm` link, is a real 404.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use bytes::Bytes;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tower::ServiceExt;
+use tower_http::services::ServeDir;
+use tower_service::Service;
+
+use crate::security::CspNonce;
+
+/// `index.html` kept in memory and reloaded whenever the file changes, so
+/// a rebuild by `trunk serve` shows up without restarting the server.
+#[derive(Clone)]
+pub struct IndexHtml {
+    current: Arc<RwLock<Bytes>>,
+    // dropping the watcher stops the reloads
+    _watcher: Option<Arc<RecommendedWatcher>>,
+}
+
+impl IndexHtml {
+    /// Read `path` and start watching it. Watching is best effort: if it
+    /// cannot be set up, the first read is served until restart.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let current = Arc::new(RwLock::new(Bytes::from(std::fs::read(path)?)));
+        let watcher = match watch(path, current.clone()) {
+            Ok(watcher) => Some(Arc::new(watcher)),
+            Err(err) => {
+                log::warn!("not reloading {} on change: {err}", path.display());
+                None
+            }
+        };
+        Ok(Self {
+            current,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn get(&self) -> Bytes {
+        self.current.read().expect("index lock poisoned").clone()
+    }
+}
+
+impl std::fmt::Debug for IndexHtml {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexHtml")
+            .field("len", &self.get().len())
+            .finish()
+    }
+}
+
+fn watch(path: &Path, current: Arc<RwLock<Bytes>>) -> notify::Result<RecommendedWatcher> {
+    let path: PathBuf = path.to_owned();
+    // build tools replace the file instead of writing to it, which ends a
+    // watch on the file itself, so watch the directory and filter instead
+    let dir = path.parent().unwrap_or(Path::new(".")).to_owned();
+    let name = path.file_name().map(ToOwned::to_owned);
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.kind.is_access() || !event.paths.iter().any(|p| p.file_name() == name.as_deref()) {
+            return;
+        }
+        match std::fs::read(&path) {
+            Ok(content) => {
+                log::info!("reloaded {}", path.display());
+                *current.write().expect("index lock poisoned") = content.into();
+            }
+            // mid-rebuild the file can be briefly missing; the write that
+            // recreates it is another event
+            Err(err) => log::debug!("keeping old {}: {err}", path.display()),
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Marks a served `index.html` with the nonce of its request in it, left in
+/// the response extensions for [`cache::apply`](crate::cache::apply). Such a
+/// body is never the same twice, and a stored copy would carry a nonce that
+/// the CSP of any later response rejects, so it must not be cached.
+#[derive(Clone, Copy, Debug)]
+pub struct Nonced;
+
+#[derive(Clone, Debug)]
+pub struct SpaFallback {
+    serve_dir: ServeDir,
+    index: IndexHtml,
+    /// html added to the end of the `<body>` of every served `index.html`
+    body_end: Option<Arc<str>>,
+}
+
+impl SpaFallback {
+    /// Fails when `static_dir` has no readable `index.html`, so a missing
+    /// frontend build stops the server instead of breaking every page.
+    pub fn new(static_dir: impl AsRef<Path>) -> io::Result<Self> {
+        let static_dir = static_dir.as_ref();
+        let index = IndexHtml::load(&static_dir.join("index.html"))?;
+
+        let serve_dir = ServeDir::new(static_dir)
+            .precompressed_br()
+            .precompressed_gzip();
+        Ok(Self {
+            serve_dir,
+            index,
+            body_end: None,
+        })
+    }
+
+    /// Add `html` at the end of the `<body>` of `index.html` whenever it is
+    /// served.
+    pub fn append_to_body(mut self, html: &str) -> Self {
+        let body_end = match self.body_end.take() {
+            Some(existing) => format!("{existing}{html}"),
+            None => html.to_owned(),
+        };
+        self.body_end = Some(body_end.into());
+        self
+    }
+
+    fn index_response(&self, nonce: Option<CspNonce>) -> Response<Body> {
+        let index = self.index.get();
+        let index = match &self.body_end {
+            None => index,
+            Some(html) => insert_before_body_end(&index, html).into(),
+        };
+        let mut res = match nonce {
+            None => Response::new(Body::from(index)),
+            Some(CspNonce(nonce)) => {
+                let mut res = Response::new(Body::from(add_script_nonce(&index, &nonce)));
+                res.extensions_mut().insert(Nonced);
+                res
+            }
+        };
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        res
+    }
+}
+
+impl Service<Request<Body>> for SpaFallback {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let navigation = is_navigation(&req);
+        let nonce = req.extensions().get::<CspNonce>().cloned();
+        let path = req.uri().path();
+        if is_read(req.method()) && (path == "/" || path == "/index.html") {
+            let res = self.index_response(nonce);
+            return Box::pin(async move { Ok(res) });
+        }
+        let this = self.clone();
+
+        Box::pin(async move {
+            let res = match this.serve_dir.clone().oneshot(req).await {
+                Ok(res) => res,
+                Err(err) => {
+                    log::error!("failed to serve static file: {err}");
+                    return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+                }
+            };
+
+            if res.status() != StatusCode::NOT_FOUND {
+                // the same path may be served with a different encoding
+                let mut res = res.map(Body::new);
+                res.headers_mut()
+                    .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+                return Ok(res);
+            }
+            if !navigation {
+                return Ok(res.map(Body::new));
+            }
+
+            let mut res = this.index_response(nonce);
+            // the same path is a 404 for anything that is not a browser page load
+            res.headers_mut()
+                .append(header::VARY, HeaderValue::from_static("accept"));
+            Ok(res)
+        })
+    }
+}
+
+/// A browser loading a page: a GET for an HTML document at a path without a
+/// file extension.
+fn is_navigation<B>(req: &Request<B>) -> bool {
+    if !is_read(req.method()) {
+        return false;
+    }
+    let accepts_html = req
+        .headers()
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("text/html"));
+    let path = req.uri().path();
+    let name = path.rsplit('/').next().unwrap_or(path);
+    accepts_html && !name.contains('.')
+}
+
+fn is_read(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD
+}
+
+fn insert_before_body_end(index: &[u8], html: &str) -> Vec<u8> {
+    let at = index
+        .windows(b"</body>".len())
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(index.len());
+    let mut out = Vec::with_capacity(index.len() + html.len());
+    out.extend_from_slice(&index[..at]);
+    out.extend_from_slice(html.as_bytes());
+    out.extend_from_slice(&index[at..]);
+    out
+}
+
+/// Add `nonce` to every `<script>` element, so a CSP with it lets them run.
+fn add_script_nonce(index: &[u8], nonce: &str) -> Vec<u8> {
+    let attribute = format!(" nonce=\"{nonce}\"");
+    let mut out = Vec::with_capacity(index.len() + 4 * attribute.len());
+    let mut rest = index;
+    while let Some(at) = rest
+        .windows(b"<script".len())
+        .position(|window| window.eq_ignore_ascii_case(b"<script"))
+    {
+        let end = at + b"<script".len();
+        out.extend_from_slice(&rest[..end]);
+        // not `<scripts>` or the like
+        if rest
+            .get(end)
+            .is_some_and(|next| next.is_ascii_whitespace() || *next == b'>')
+        {
+            out.extend_from_slice(attribute.as_bytes());
+        }
+        rest = &rest[end..];
+    }
+    out.extend_from_slice(rest);
+    out
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str =
+        "<!DOCTYPE html><html><body><script type=\"module\">init()</script></body></html>";
+
+    /// A static dir with an `index.html` and `files`, as (path, contents).
+    fn static_dir(files: &[(&str, &[u8])]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), INDEX).unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    async fn get(spa: &SpaFallback, path: &str, headers: &[(&str, &str)]) -> Response<Body> {
+        let mut req = Request::get(path);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        spa.clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn body(res: Response<Body>) -> Bytes {
+        axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    fn header(res: &Response<Body>, name: header::HeaderName) -> Option<&str> {
+        res.headers().get(name).map(|value| value.to_str().unwrap())
+    }
+
+    fn varies_on(res: &Response<Body>, name: &str) -> bool {
+        res.headers()
+            .get_all(header::VARY)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap().split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(name))
+    }
+
+    #[tokio::test]
+    async fn precompressed_siblings() {
+        let dir = static_dir(&[
+            ("app.js", b"identity"),
+            ("app.js.br", b"brotli"),
+            ("app.js.gz", b"gzip"),
+        ]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+
+        let res = get(&spa, "/app.js", &[("accept-encoding", "br")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(header(&res, header::CONTENT_ENCODING), Some("br"));
+        assert!(varies_on(&res, "accept-encoding"));
+        assert_eq!(body(res).await, "brotli");
+
+        let res = get(&spa, "/app.js", &[("accept-encoding", "gzip")]).await;
+        assert_eq!(header(&res, header::CONTENT_ENCODING), Some("gzip"));
+        assert!(varies_on(&res, "accept-encoding"));
+        assert_eq!(body(res).await, "gzip");
+
+        let res = get(&spa, "/app.js", &[]).await;
+        assert_eq!(header(&res, header::CONTENT_ENCODING), None);
+        assert!(varies_on(&res, "accept-encoding"));
+        assert_eq!(body(res).await, "identity");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_identity_without_sibling() {
+        let dir = static_dir(&[("style.css", b"identity"), ("style.css.gz", b"gzip")]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+
+        let res = get(&spa, "/style.css", &[("accept-encoding", "br")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(header(&res, header::CONTENT_ENCODING), None);
+        assert!(varies_on(&res, "accept-encoding"));
+        assert_eq!(body(res).await, "identity");
+    }
+
+    #[tokio::test]
+    async fn navigation_gets_index() {
+        let dir = static_dir(&[]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+
+        let res = get(&spa, "/posts/hello", &[("accept", "text/html,*/*;q=0.8")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            header(&res, header::CONTENT_TYPE),
+            Some("text/html; charset=utf-8")
+        );
+        assert!(varies_on(&res, "accept"));
+        assert_eq!(body(res).await, INDEX);
+    }
+
+    #[tokio::test]
+    async fn missing_assets_are_404() {
+        let dir = static_dir(&[]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+
+        for path in ["/frontend-6a1f0b2c9d3e4f57.js", "/frontend_bg.wasm"] {
+            let res = get(&spa, path, &[("accept", "text/html,*/*;q=0.8")]).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn non_html_accept_is_404() {
+        let dir = static_dir(&[]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+
+        for accept in [None, Some("application/json"), Some("*/*")] {
+            let headers: Vec<_> = accept
+                .map(|accept| ("accept", accept))
+                .into_iter()
+                .collect();
+            let res = get(&spa, "/posts/hello", &headers).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{accept:?}");
+        }
+    }
+
+    #[test]
+    fn new_needs_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = SpaFallback::new(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn index_is_served_from_memory() {
+        let dir = static_dir(&[]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+        assert_eq!(body(get(&spa, "/", &[]).await).await, INDEX);
+
+        // any read from here on would fail
+        std::fs::remove_file(dir.path().join("index.html")).unwrap();
+        // and the watcher sees the removal without dropping the old index
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        for _ in 0..100 {
+            let res = get(&spa, "/", &[]).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(body(res).await, INDEX);
+        }
+        let res = get(&spa, "/posts/hello", &[("accept", "text/html")]).await;
+        assert_eq!(body(res).await, INDEX);
+    }
+
+    #[tokio::test]
+    async fn index_reloads_on_change() {
+        let dir = static_dir(&[]);
+        let spa = SpaFallback::new(dir.path()).unwrap();
+        assert_eq!(body(get(&spa, "/", &[]).await).await, INDEX);
+
+        let rebuilt = "<!DOCTYPE html><html><body>rebuilt</body></html>";
+        // like trunk, replace the file rather than write to it
+        let tmp = dir.path().join("index.html.tmp");
+        std::fs::write(&tmp, rebuilt).unwrap();
+        std::fs::rename(&tmp, dir.path().join("index.html")).unwrap();
+
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            if body(get(&spa, "/", &[]).await).await == rebuilt {
+                break;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "index was not reloaded"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    }
+}
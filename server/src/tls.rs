@@ -0,0 +1,217 @@
+//! HTTPS for self hosting without a reverse proxy.
+//!
+//! The certificate is reloaded whenever its files change, so renewals (e.g.
+//! by certbot) take effect without a restart. An optional plain HTTP listener
+//! redirects everything to HTTPS.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use axum::extract::Request;
+use axum::http::{header, uri::Authority, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert: PathBuf,
+    /// PEM private key
+    pub key: PathBuf,
+}
+
+/// Load the certificate and key from disk.
+pub async fn load(tls: &TlsConfig) -> io::Result<RustlsConfig> {
+    // ring is the only provider compiled in, but rustls still wants it named
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(&tls.cert, &tls.key).await
+}
+
+/// Reload `config` whenever the certificate or key file changes, for as long
+/// as the returned watcher lives. Must be called within a tokio runtime.
+pub fn watch(config: RustlsConfig, tls: &TlsConfig) -> notify::Result<RecommendedWatcher> {
+    let runtime = tokio::runtime::Handle::current();
+    let tls_in_handler = tls.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let tls = &tls_in_handler;
+        let touches_ours = event
+            .paths
+            .iter()
+            .any(|path| same_file_name(path, &tls.cert) || same_file_name(path, &tls.key));
+        if event.kind.is_access() || !touches_ours {
+            return;
+        }
+
+        let config = config.clone();
+        let tls = tls.clone();
+        runtime.spawn(async move {
+            match config.reload_from_pem_file(&tls.cert, &tls.key).await {
+                Ok(()) => log::info!("reloaded certificate {}", tls.cert.display()),
+                // renewals write cert and key separately, so a mismatched pair
+                // is expected until the second write arrives
+                Err(err) => log::warn!("keeping old certificate: {err}"),
+            }
+        });
+    })?;
+
+    // renewals usually replace the files, which ends a watch on the files
+    // themselves, so watch their directories instead
+    for path in [&tls.cert, &tls.key] {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        watcher.watch(dir.unwrap_or(Path::new(".")), RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
+
+fn same_file_name(a: &Path, b: &Path) -> bool {
+    a.file_name().is_some() && a.file_name() == b.file_name()
+}
+
+/// An app that answers every request with a permanent redirect to the same
+/// path on `https_port`.
+pub fn redirect_app(https_port: u16) -> Router {
+    Router::new().fallback(move |req: Request| async move { redirect(req, https_port) })
+}
+
+fn redirect(req: Request, https_port: u16) -> Response {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+        .or_else(|| req.uri().authority().cloned());
+    let Some(host) = host else {
+        return (StatusCode::BAD_REQUEST, "missing host").into_response();
+    };
+
+    let authority = match https_port {
+        443 => host.host().to_owned(),
+        port => format!("{}:{port}", host.host()),
+    };
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let location = Uri::builder()
+        .scheme("https")
+        .authority(authority)
+        .path_and_query(path_and_query)
+        .build();
+
+    match location
+        .ok()
+        .and_then(|uri| HeaderValue::from_str(&uri.to_string()).ok())
+    {
+        Some(location) => (
+            StatusCode::MOVED_PERMANENTLY,
+            [(header::LOCATION, location)],
+        )
+            .into_response(),
+        None => (StatusCode::BAD_REQUEST, "invalid host").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn location(https_port: u16, host: &str, path: &str) -> String {
+        let req = Request::get(path)
+            .header(header::HOST, host)
+            .body(Body::empty())
+            .unwrap();
+        let res = redirect_app(https_port).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        res.headers()[header::LOCATION].to_str().unwrap().to_owned()
+    }
+
+    #[tokio::test]
+    async fn redirect_keeps_path_and_query() {
+        assert_eq!(
+            location(8443, "example.com:8080", "/posts/a?tag=x&b=%20").await,
+            "https://example.com:8443/posts/a?tag=x&b=%20"
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_leaves_out_default_port() {
+        assert_eq!(
+            location(443, "example.com:80", "/").await,
+            "https://example.com/"
+        );
+        assert_eq!(
+            location(443, "example.com", "/a?b").await,
+            "https://example.com/a?b"
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_ipv6_host() {
+        assert_eq!(
+            location(8443, "[::1]:8080", "/a").await,
+            "https://[::1]:8443/a"
+        );
+        assert_eq!(location(443, "[::1]", "/").await, "https://[::1]/");
+    }
+
+    #[tokio::test]
+    async fn redirect_needs_host() {
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let res = redirect_app(443).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Write a fresh self-signed certificate for localhost into `dir`.
+    fn write_cert(dir: &Path) -> TlsConfig {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let tls = TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        };
+        std::fs::write(&tls.cert, generated.cert.pem()).unwrap();
+        std::fs::write(&tls.key, generated.signing_key.serialize_pem()).unwrap();
+        tls
+    }
+
+    #[tokio::test]
+    async fn load_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let tls = write_cert(dir.path());
+        let config = load(&tls).await.unwrap();
+
+        // a renewal
+        let tls = write_cert(dir.path());
+        config
+            .reload_from_pem_file(&tls.cert, &tls.key)
+            .await
+            .unwrap();
+
+        // half way through one, the key no longer matches
+        let old_key = std::fs::read(&tls.key).unwrap();
+        write_cert(dir.path());
+        std::fs::write(&tls.key, old_key).unwrap();
+        assert!(config
+            .reload_from_pem_file(&tls.cert, &tls.key)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn load_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let tls = TlsConfig {
+            cert: dir.path().join("cert.pem"),
+            key: dir.path().join("key.pem"),
+        };
+        assert!(load(&tls).await.is_err());
+    }
+}
@@ -0,0 +1,994 @@
+//! `server emulate-vercel`: serving the way a vercel deployment of
+//! `vercel.json` does, to find routing mistakes before deploying.
+//!
+//! Requests are routed like the platform routes them, either by `routes`
+//! or by `headers`, `redirects` and `rewrites` with the filesystem checked
+//! between the last two. Files are served from the directory of
+//! `vercel.json`, and every function destination runs the app in-process
+//! behind the same [`LambdaLayer`](crate::lambda::LambdaLayer) as on vercel,
+//! from the event the platform would send it. The function sees the original
+//! path, as on vercel, so only `vercel.json` decides what reaches it.
+//!
+//! Features that change nothing about where a request ends up locally, like
+//! `has` conditions, `cleanUrls` or external rewrites, are not emulated.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use base64::Engine;
+use lambda_runtime::{Context, FunctionResponse, LambdaEvent};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tower::{Layer, ServiceExt};
+use tower_http::services::ServeFile;
+use vercel_runtime::request::VercelEvent;
+use vercel_runtime::{process_request, Body as LambdaBody};
+
+use crate::lambda::{self, LambdaResponse, LambdaService};
+use crate::Config;
+
+/// Why `vercel.json` cannot be emulated.
+#[derive(Debug)]
+pub enum VercelJsonError {
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl fmt::Display for VercelJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VercelJsonError::Read { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            VercelJsonError::Parse { path, source } => {
+                write!(f, "failed to parse {}: {source}", path.display())
+            }
+            VercelJsonError::Invalid { path, reason } => {
+                write!(f, "invalid {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for VercelJsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VercelJsonError::Read { source, .. } => Some(source),
+            VercelJsonError::Parse { source, .. } => Some(source),
+            VercelJsonError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// The parts of `vercel.json` that route requests.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VercelJson {
+    #[serde(default)]
+    functions: BTreeMap<String, serde::de::IgnoredAny>,
+    routes: Option<Vec<RouteJson>>,
+    #[serde(default)]
+    headers: Vec<HeadersJson>,
+    #[serde(default)]
+    redirects: Vec<RedirectJson>,
+    #[serde(default)]
+    rewrites: Vec<RewriteJson>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RouteJson {
+    src: Option<String>,
+    dest: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    status: Option<u16>,
+    methods: Option<Vec<String>>,
+    #[serde(default)]
+    r#continue: bool,
+    handle: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HeadersJson {
+    source: String,
+    headers: Vec<HeaderJson>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HeaderJson {
+    key: String,
+    value: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RedirectJson {
+    source: String,
+    destination: String,
+    permanent: Option<bool>,
+    status_code: Option<u16>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RewriteJson {
+    source: String,
+    destination: String,
+}
+
+/// `vercel.json`, compiled for routing.
+#[derive(Debug)]
+pub struct Routing {
+    /// the directory of `vercel.json`, which files are served from
+    root: PathBuf,
+    functions: Vec<String>,
+    rules: Rules,
+}
+
+#[derive(Debug)]
+enum Rules {
+    Routes(Vec<Route>),
+    Modern {
+        headers: Vec<(Regex, Vec<(String, String)>)>,
+        redirects: Vec<(Regex, String, StatusCode)>,
+        rewrites: Vec<(Regex, String)>,
+    },
+}
+
+#[derive(Debug)]
+enum Route {
+    /// `{"handle": "filesystem"}`: serve the path if it is a file
+    Filesystem,
+    Match {
+        src: Regex,
+        dest: Option<String>,
+        headers: Vec<(String, String)>,
+        status: Option<StatusCode>,
+        /// uppercase, `None` for all
+        methods: Option<Vec<String>>,
+        continues: bool,
+    },
+}
+
+/// Where a request ends up.
+#[derive(Debug)]
+enum Target {
+    /// the file at this path below the root, if there is one
+    File {
+        path: String,
+        status: Option<StatusCode>,
+    },
+    /// the app, with `query` added to the one of the request
+    Function {
+        query: Option<String>,
+        status: Option<StatusCode>,
+    },
+    /// only a status, like a redirect with its `Location`
+    Status(StatusCode),
+    External(String),
+    NotFound,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::File { path, .. } => write!(f, "file {path}"),
+            Target::Function { .. } => write!(f, "function"),
+            Target::Status(status) => write!(f, "status {}", status.as_u16()),
+            Target::External(url) => write!(f, "external {url}"),
+            Target::NotFound => write!(f, "not found"),
+        }
+    }
+}
+
+impl Routing {
+    pub fn load(path: &Path) -> Result<Routing, VercelJsonError> {
+        let text = std::fs::read_to_string(path).map_err(|source| VercelJsonError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        let json: VercelJson =
+            serde_json::from_str(&text).map_err(|source| VercelJsonError::Parse {
+                path: path.to_owned(),
+                source,
+            })?;
+        let root = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        Routing::compile(json, root).map_err(|reason| VercelJsonError::Invalid {
+            path: path.to_owned(),
+            reason,
+        })
+    }
+
+    fn compile(json: VercelJson, root: PathBuf) -> Result<Routing, String> {
+        let functions = json.functions.into_keys().collect();
+        let Some(routes) = json.routes else {
+            return Ok(Routing {
+                root,
+                functions,
+                rules: Rules::Modern {
+                    headers: json
+                        .headers
+                        .into_iter()
+                        .map(|rule| {
+                            let headers = rule
+                                .headers
+                                .into_iter()
+                                .map(|header| (header.key, header.value))
+                                .collect();
+                            Ok((source_regex(&rule.source)?, headers))
+                        })
+                        .collect::<Result<_, String>>()?,
+                    redirects: json
+                        .redirects
+                        .into_iter()
+                        .map(|rule| {
+                            let status = match (rule.status_code, rule.permanent) {
+                                (Some(code @ (301 | 302 | 303 | 307 | 308)), _) => {
+                                    StatusCode::from_u16(code).expect("a redirect status")
+                                }
+                                (Some(code), _) => {
+                                    return Err(format!(
+                                        "redirect of {} has status {code}, which is no redirect",
+                                        rule.source
+                                    ))
+                                }
+                                (None, Some(false)) => StatusCode::TEMPORARY_REDIRECT,
+                                (None, _) => StatusCode::PERMANENT_REDIRECT,
+                            };
+                            Ok((source_regex(&rule.source)?, rule.destination, status))
+                        })
+                        .collect::<Result<_, String>>()?,
+                    rewrites: json
+                        .rewrites
+                        .into_iter()
+                        .map(|rule| Ok((source_regex(&rule.source)?, rule.destination)))
+                        .collect::<Result<_, String>>()?,
+                },
+            });
+        };
+
+        if !(json.headers.is_empty() && json.redirects.is_empty() && json.rewrites.is_empty()) {
+            return Err(
+                "`routes` cannot be used together with `headers`, `redirects` or `rewrites`"
+                    .to_owned(),
+            );
+        }
+        let routes = routes
+            .into_iter()
+            .map(|route| {
+                match route.handle.as_deref() {
+                    Some("filesystem") => return Ok(Route::Filesystem),
+                    Some(handle) => return Err(format!("`handle: {handle}` is not emulated")),
+                    None => {}
+                }
+                let src = route
+                    .src
+                    .ok_or_else(|| "a route has neither `src` nor `handle`".to_owned())?;
+                // vercel anchors the pattern and ignores case
+                let regex = Regex::new(&format!("(?i)^(?:{src})$"))
+                    .map_err(|err| format!("route {src}: {err}"))?;
+                let status = route
+                    .status
+                    .map(|status| {
+                        StatusCode::from_u16(status)
+                            .map_err(|_| format!("route {src} has status {status}"))
+                    })
+                    .transpose()?;
+                Ok(Route::Match {
+                    src: regex,
+                    dest: route.dest,
+                    headers: route.headers.into_iter().collect(),
+                    status,
+                    methods: route
+                        .methods
+                        .map(|methods| methods.iter().map(|m| m.to_ascii_uppercase()).collect()),
+                    continues: route.r#continue,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Routing {
+            root,
+            functions,
+            rules: Rules::Routes(routes),
+        })
+    }
+
+    /// Where a request for `uri` ends up, with the headers to add to its
+    /// response. `is_file` says whether there is a file for a path.
+    fn route(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        is_file: impl Fn(&str) -> bool,
+    ) -> (Target, Vec<(String, String)>) {
+        let path = uri.path();
+        let mut headers = Vec::new();
+        match &self.rules {
+            Rules::Routes(routes) => {
+                let mut path = path.to_owned();
+                let mut query = None;
+                for route in routes {
+                    let Route::Match {
+                        src,
+                        dest,
+                        headers: add,
+                        status,
+                        methods,
+                        continues,
+                    } = route
+                    else {
+                        if is_file(&path) {
+                            return (Target::File { path, status: None }, headers);
+                        }
+                        continue;
+                    };
+                    if methods
+                        .as_ref()
+                        .is_some_and(|methods| !methods.iter().any(|m| m == method.as_str()))
+                    {
+                        continue;
+                    }
+                    let Some(caps) = src.captures(&path) else {
+                        continue;
+                    };
+                    headers.extend(
+                        add.iter()
+                            .map(|(name, value)| (name.clone(), expand(&caps, value))),
+                    );
+                    let dest = dest.as_ref().map(|dest| expand(&caps, dest));
+                    if *continues {
+                        if let Some(dest) = dest {
+                            let (dest_path, dest_query) = split_query(&dest);
+                            path = dest_path.to_owned();
+                            query = join_query(query.as_deref(), dest_query);
+                        }
+                        continue;
+                    }
+                    let target = match dest {
+                        Some(dest) => self.destination(&dest, query.as_deref(), *status),
+                        None => match status {
+                            Some(status) => Target::Status(*status),
+                            None => Target::File { path, status: None },
+                        },
+                    };
+                    return (target, headers);
+                }
+                if is_file(&path) {
+                    (Target::File { path, status: None }, headers)
+                } else {
+                    (Target::NotFound, headers)
+                }
+            }
+            Rules::Modern {
+                headers: header_rules,
+                redirects,
+                rewrites,
+            } => {
+                for (source, add) in header_rules {
+                    if let Some(caps) = source.captures(path) {
+                        headers.extend(
+                            add.iter()
+                                .map(|(name, value)| (name.clone(), expand(&caps, value))),
+                        );
+                    }
+                }
+                for (source, destination, status) in redirects {
+                    if let Some(caps) = source.captures(path) {
+                        // the query goes along, as on vercel
+                        let location = expand(&caps, destination);
+                        let (location_path, location_query) = split_query(&location);
+                        let location = match join_query(location_query, uri.query()) {
+                            Some(query) => format!("{location_path}?{query}"),
+                            None => location_path.to_owned(),
+                        };
+                        headers.push(("location".to_owned(), location));
+                        return (Target::Status(*status), headers);
+                    }
+                }
+                if is_file(path) {
+                    let path = path.to_owned();
+                    return (Target::File { path, status: None }, headers);
+                }
+                for (source, destination) in rewrites {
+                    if let Some(caps) = source.captures(path) {
+                        let dest = expand(&caps, destination);
+                        return (self.destination(&dest, None, None), headers);
+                    }
+                }
+                (Target::NotFound, headers)
+            }
+        }
+    }
+
+    /// What `dest` names: a function, another site or a file.
+    fn destination(&self, dest: &str, query: Option<&str>, status: Option<StatusCode>) -> Target {
+        if dest.starts_with("http://") || dest.starts_with("https://") {
+            return Target::External(dest.to_owned());
+        }
+        let (path, dest_query) = split_query(dest);
+        let query = join_query(query, dest_query);
+        let file = path.trim_start_matches('/');
+        if self.functions.iter().any(|function| function == file) || file.starts_with("api/") {
+            return Target::Function { query, status };
+        }
+        Target::File {
+            path: format!("/{file}"),
+            status,
+        }
+    }
+
+    /// The file below the root for a request path, `index.html` for
+    /// directories, if there is one.
+    fn file(&self, path: &str) -> Option<PathBuf> {
+        let decoded = percent_encoding::percent_decode_str(path)
+            .decode_utf8()
+            .ok()?;
+        let mut file = self.root.clone();
+        for component in Path::new(decoded.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(name) => file.push(name),
+                Component::CurDir => {}
+                // no way out of the root
+                _ => return None,
+            }
+        }
+        if file.is_dir() {
+            file.push("index.html");
+        }
+        file.is_file().then_some(file)
+    }
+}
+
+/// The regex for a `source` of `headers`, `redirects` and `rewrites`, which
+/// are path-to-regexp patterns: `:name` matches a segment, `:name*` and
+/// `:name+` any number of them, `:name?` an optional one, and `(...)` a
+/// regex of its own. `:name(...)` names such a regex.
+fn source_regex(source: &str) -> Result<Regex, String> {
+    let mut re = String::from("^");
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ':' => {
+                let mut name = String::new();
+                while let Some(&c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    name.push(c);
+                    chars.next();
+                }
+                if name.is_empty() {
+                    return Err(format!("source {source} has a `:` without a name"));
+                }
+                let pattern = if chars.peek() == Some(&'(') {
+                    chars.next();
+                    group(&mut chars)
+                        .ok_or_else(|| format!("source {source} has an unclosed `(`"))?
+                } else {
+                    "[^/]+".to_owned()
+                };
+                let modifier = chars.next_if(|c| matches!(c, '*' | '+' | '?'));
+                // an optional parameter takes the `/` before it along
+                let prefix = if matches!(modifier, Some('*' | '?')) && re.ends_with('/') {
+                    re.pop();
+                    "/"
+                } else {
+                    ""
+                };
+                let param = match modifier {
+                    Some('*' | '+') => format!("(?P<{name}>{pattern}(?:/{pattern})*)"),
+                    _ => format!("(?P<{name}>{pattern})"),
+                };
+                match modifier {
+                    Some('*' | '?') => re.push_str(&format!("(?:{prefix}{param})?")),
+                    _ => re.push_str(&param),
+                }
+            }
+            '(' => {
+                let pattern = group(&mut chars)
+                    .ok_or_else(|| format!("source {source} has an unclosed `(`"))?;
+                re.push_str(&format!("({pattern})"));
+            }
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).map_err(|err| format!("source {source}: {err}"))
+}
+
+/// The rest of a `(...)` group whose `(` was taken, without its `)`.
+fn group(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let mut pattern = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                pattern.push(c);
+                pattern.push(chars.next()?);
+                continue;
+            }
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(pattern),
+            ')' => depth -= 1,
+            _ => {}
+        }
+        pattern.push(c);
+    }
+    None
+}
+
+/// `template` with `$1` and `$name` replaced by what was captured, and
+/// `:name` too for the names in the pattern.
+fn expand(caps: &Captures, template: &str) -> String {
+    static PARAM: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r":([A-Za-z0-9_]+)[*+?]?").expect("a valid regex"));
+    let template = PARAM.replace_all(template, |param: &Captures| {
+        if caps.name(&param[1]).is_some() {
+            format!("${{{}}}", &param[1])
+        } else {
+            param[0].to_owned()
+        }
+    });
+    let mut expanded = String::new();
+    caps.expand(&template, &mut expanded);
+    expanded
+}
+
+fn split_query(uri: &str) -> (&str, Option<&str>) {
+    match uri.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (uri, None),
+    }
+}
+
+fn join_query(a: Option<&str>, b: Option<&str>) -> Option<String> {
+    match (a.filter(|a| !a.is_empty()), b.filter(|b| !b.is_empty())) {
+        (Some(a), Some(b)) => Some(format!("{a}&{b}")),
+        (a, b) => a.or(b).map(str::to_owned),
+    }
+}
+
+struct Emulator {
+    routing: Routing,
+    function: LambdaService<Router>,
+}
+
+/// The emulated deployment, with `app` as its function.
+pub fn emulator(routing: Routing, app: Router, config: &Config) -> Router {
+    let function = lambda::layer(config).layer(app);
+    Router::new()
+        .fallback(handle)
+        .with_state(Arc::new(Emulator { routing, function }))
+}
+
+async fn handle(State(emulator): State<Arc<Emulator>>, req: Request) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let (parts, body) = req.into_parts();
+    let routing = &emulator.routing;
+    let (target, headers) = routing.route(&parts.method, &parts.uri, |path| {
+        routing.file(path).is_some()
+    });
+    log::info!("{} {} -> {target}", parts.method, parts.uri);
+
+    let mut res = match target {
+        Target::File { path, status } => match routing.file(&path) {
+            Some(file) => {
+                let mut req = Request::new(Body::empty());
+                *req.method_mut() = parts.method.clone();
+                *req.headers_mut() = parts.headers.clone();
+                let mut res = ServeFile::new(file)
+                    .oneshot(req)
+                    .await
+                    .expect("ServeFile is infallible")
+                    .into_response();
+                if let Some(status) = status {
+                    *res.status_mut() = status;
+                }
+                res
+            }
+            None => vercel_error(StatusCode::NOT_FOUND, "NOT_FOUND"),
+        },
+        Target::Function { query, status } => {
+            let mut res = invoke(&emulator.function, parts, body, query, peer).await;
+            if let Some(status) = status {
+                *res.status_mut() = status;
+            }
+            res
+        }
+        Target::Status(status) => status.into_response(),
+        Target::External(url) => {
+            log::warn!("not proxying to {url}, external destinations are not emulated");
+            vercel_error(StatusCode::BAD_GATEWAY, "EXTERNAL_DESTINATION_NOT_EMULATED")
+        }
+        Target::NotFound => vercel_error(StatusCode::NOT_FOUND, "NOT_FOUND"),
+    };
+    for (name, value) in headers {
+        match (HeaderName::try_from(&name), HeaderValue::try_from(&value)) {
+            (Ok(name), Ok(value)) => {
+                res.headers_mut().insert(name, value);
+            }
+            _ => log::warn!("not setting invalid header {name}: {value}"),
+        }
+    }
+    res
+}
+
+/// Runs the app on the event vercel would send for the request.
+async fn invoke(
+    function: &LambdaService<Router>,
+    parts: Parts,
+    body: Body,
+    query: Option<String>,
+    peer: Option<SocketAddr>,
+) -> Response {
+    let body = match axum::body::to_bytes(body, lambda::MAX_BODY).await {
+        Ok(body) => body,
+        Err(_) => return vercel_error(StatusCode::PAYLOAD_TOO_LARGE, "FUNCTION_PAYLOAD_TOO_LARGE"),
+    };
+    let path = match join_query(parts.uri.query(), query.as_deref()) {
+        Some(query) => format!("{}?{query}", parts.uri.path()),
+        None => parts.uri.path().to_owned(),
+    };
+    let host = parts
+        .headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost")
+        .to_owned();
+
+    let mut headers = event_headers(&parts.headers);
+    if let Some(peer) = peer {
+        let ip = peer.ip().to_canonical().to_string();
+        for name in ["x-forwarded-for", "x-real-ip", "x-vercel-forwarded-for"] {
+            headers.insert(name.to_owned(), Value::String(ip.clone()));
+        }
+    }
+    headers.insert("x-forwarded-host".to_owned(), Value::String(host.clone()));
+    headers.insert("x-forwarded-proto".to_owned(), "http".into());
+    let request = json!({
+        "host": host,
+        "path": path,
+        "method": parts.method.as_str(),
+        "headers": headers,
+        "body": base64::engine::general_purpose::STANDARD.encode(&body),
+        "encoding": "base64",
+    });
+    let event = VercelEvent {
+        action: "Invoke".into(),
+        body: request.to_string().into(),
+    };
+    let req = process_request(LambdaEvent::new(event, Context::default()));
+
+    match function.clone().oneshot(req).await {
+        Ok(res) => from_lambda(res),
+        Err(err) => {
+            log::error!("function failed: {err}");
+            vercel_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "FUNCTION_INVOCATION_FAILED",
+            )
+        }
+    }
+}
+
+/// The headers of the event, with repeated ones joined.
+fn event_headers(headers: &HeaderMap) -> Map<String, Value> {
+    let mut map = Map::new();
+    for name in headers.keys() {
+        let values: Vec<_> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if !values.is_empty() {
+            let separator = if name == header::COOKIE { "; " } else { ", " };
+            map.insert(name.to_string(), Value::String(values.join(separator)));
+        }
+    }
+    map
+}
+
+/// The response the platform makes of what the function answered.
+fn from_lambda(res: LambdaResponse) -> Response {
+    match res {
+        FunctionResponse::BufferedResponse(res) => {
+            let body = match res.body {
+                None | Some(LambdaBody::Empty) => Body::empty(),
+                Some(LambdaBody::Text(text)) => text.into(),
+                Some(LambdaBody::Binary(bytes)) => bytes.into(),
+            };
+            let mut response = Response::new(body);
+            *response.status_mut() =
+                StatusCode::from_u16(res.status_code).unwrap_or(StatusCode::BAD_GATEWAY);
+            *response.headers_mut() = res.headers;
+            response
+        }
+        FunctionResponse::StreamingResponse(res) => {
+            let prelude = res.metadata_prelude;
+            let mut response = Response::new(Body::from_stream(res.stream));
+            *response.status_mut() = prelude.status_code;
+            *response.headers_mut() = prelude.headers;
+            for cookie in prelude.cookies {
+                match HeaderValue::try_from(cookie) {
+                    Ok(cookie) => {
+                        response.headers_mut().append(header::SET_COOKIE, cookie);
+                    }
+                    Err(err) => log::warn!("dropping invalid cookie: {err}"),
+                }
+            }
+            response
+        }
+    }
+}
+
+/// An error of the platform itself, which answers them as plain text with
+/// their code.
+fn vercel_error(status: StatusCode, code: &'static str) -> Response {
+    let reason = status.canonical_reason().unwrap_or("Error");
+    (
+        status,
+        [(HeaderName::from_static("x-vercel-error"), code)],
+        format!("{reason}\n\n{code}\n"),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The captures of `source` on `path`, by name.
+    fn captures(source: &str, path: &str) -> Option<BTreeMap<String, String>> {
+        let regex = source_regex(source).unwrap();
+        let caps = regex.captures(path)?;
+        let names = regex.capture_names().flatten();
+        Some(
+            names
+                .filter_map(|name| Some((name.to_owned(), caps.name(name)?.as_str().to_owned())))
+                .collect(),
+        )
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Option<BTreeMap<String, String>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn source_param() {
+        let source = "/posts/:slug";
+        assert_eq!(captures(source, "/posts/a-b"), params(&[("slug", "a-b")]));
+        assert_eq!(captures(source, "/posts/a/b"), None);
+        assert_eq!(captures(source, "/posts/"), None);
+        assert_eq!(captures(source, "/posts"), None);
+    }
+
+    #[test]
+    fn source_param_star() {
+        let source = "/docs/:path*";
+        assert_eq!(captures(source, "/docs"), params(&[]));
+        assert_eq!(captures(source, "/docs/a"), params(&[("path", "a")]));
+        assert_eq!(captures(source, "/docs/a/b"), params(&[("path", "a/b")]));
+        assert_eq!(captures(source, "/docsa"), None);
+
+        let source = "/files/:path+";
+        assert_eq!(captures(source, "/files/a/b"), params(&[("path", "a/b")]));
+        assert_eq!(captures(source, "/files"), None);
+    }
+
+    #[test]
+    fn source_param_optional() {
+        let source = "/blog/:page?";
+        assert_eq!(captures(source, "/blog"), params(&[]));
+        assert_eq!(captures(source, "/blog/2"), params(&[("page", "2")]));
+        assert_eq!(captures(source, "/blog/2/3"), None);
+    }
+
+    #[test]
+    fn source_param_regex() {
+        let source = r"/item/:id(\d+)";
+        assert_eq!(captures(source, "/item/42"), params(&[("id", "42")]));
+        assert_eq!(captures(source, "/item/abc"), None);
+
+        let source = r"/item/:id(\d+)*";
+        assert_eq!(captures(source, "/item/4/2"), params(&[("id", "4/2")]));
+        assert_eq!(captures(source, "/item/4/b"), None);
+    }
+
+    #[test]
+    fn source_literals() {
+        assert_eq!(captures("/old/(.*)", "/old/a/b"), params(&[]));
+        assert_eq!(captures("/robots.txt", "/robots.txt"), params(&[]));
+        assert_eq!(captures("/robots.txt", "/robotsxtxt"), None);
+        assert!(source_regex("/:").is_err());
+        assert!(source_regex(r"/:id(\d+").is_err());
+        assert!(source_regex("/(a").is_err());
+    }
+
+    #[test]
+    fn expand_params() {
+        let regex = source_regex("/posts/:slug/(.*)").unwrap();
+        let caps = regex.captures("/posts/a/b/c").unwrap();
+        assert_eq!(expand(&caps, "/blog/:slug"), "/blog/a");
+        assert_eq!(expand(&caps, "/blog/$slug/$2"), "/blog/a/b/c");
+        assert_eq!(
+            expand(&caps, "/blog?post=:slug&x=:other"),
+            "/blog?post=a&x=:other"
+        );
+    }
+
+    fn routing(json: &str) -> Routing {
+        Routing::compile(serde_json::from_str(json).unwrap(), PathBuf::from(".")).unwrap()
+    }
+
+    /// Where a GET for `uri` ends up, with `files` being the only files.
+    fn route(routing: &Routing, uri: &str, files: &[&str]) -> (Target, Vec<(String, String)>) {
+        let uri: Uri = uri.parse().unwrap();
+        routing.route(&Method::GET, &uri, |path| files.contains(&path))
+    }
+
+    fn target(routing: &Routing, uri: &str, files: &[&str]) -> String {
+        route(routing, uri, files).0.to_string()
+    }
+
+    #[test]
+    fn routes_of_the_repo() {
+        let routing = Routing::load(Path::new("vercel.json")).unwrap();
+        for uri in [
+            "/api/posts",
+            "/api/posts/a?tag=b",
+            "/API/health",
+            "/feed.xml",
+            "/atom.xml",
+            "/feed.json",
+            "/sitemap.xml",
+            "/robots.txt",
+        ] {
+            assert_eq!(target(&routing, uri, &[]), "function", "{uri}");
+        }
+        assert_eq!(target(&routing, "/", &[]), "file /dist/");
+        assert_eq!(
+            target(&routing, "/frontend-6a1f0b2c9d3e4f57.js", &[]),
+            "file /dist/frontend-6a1f0b2c9d3e4f57.js"
+        );
+        assert_eq!(target(&routing, "/posts/a", &[]), "file /dist/posts/a");
+        // `/api` itself is not below it
+        assert_eq!(target(&routing, "/api", &[]), "file /dist/api");
+        assert_eq!(target(&routing, "/feed.xmlx", &[]), "file /dist/feed.xmlx");
+    }
+
+    #[test]
+    fn routes_continue_and_filesystem() {
+        let routing = routing(
+            r#"{"routes": [
+                {"src": "/(.*)", "headers": {"x-path": "$1"}, "continue": true},
+                {"src": "/old/(.*)", "dest": "/new/$1?from=old", "continue": true},
+                {"handle": "filesystem"},
+                {"src": "/new/(.*)", "status": 410},
+                {"src": "/.*", "dest": "/index.html"}
+            ]}"#,
+        );
+        let (file, headers) = route(&routing, "/app.js", &["/app.js"]);
+        assert_eq!(file.to_string(), "file /app.js");
+        assert_eq!(headers, [("x-path".to_owned(), "app.js".to_owned())]);
+
+        assert_eq!(target(&routing, "/old/a", &["/new/a"]), "file /new/a");
+        assert_eq!(target(&routing, "/old/a", &[]), "status 410");
+        assert_eq!(target(&routing, "/about", &[]), "file /index.html");
+    }
+
+    fn redirect(routing: &Routing, uri: &str) -> (StatusCode, String) {
+        match route(routing, uri, &[]) {
+            (Target::Status(status), headers) => {
+                let location = headers
+                    .into_iter()
+                    .find(|(name, _)| name == "location")
+                    .map(|(_, location)| location)
+                    .unwrap();
+                (status, location)
+            }
+            (target, _) => panic!("{uri} is no redirect but {target}"),
+        }
+    }
+
+    #[test]
+    fn redirects_keep_the_query() {
+        let routing = routing(
+            r#"{
+                "redirects": [
+                    {"source": "/old/:slug", "destination": "/posts/:slug"},
+                    {"source": "/s", "destination": "/search?ref=old", "permanent": false},
+                    {"source": "/away", "destination": "https://example.com/", "statusCode": 302}
+                ]
+            }"#,
+        );
+        assert_eq!(
+            redirect(&routing, "/old/a?tag=x&page=2"),
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                "/posts/a?tag=x&page=2".to_owned()
+            )
+        );
+        assert_eq!(
+            redirect(&routing, "/old/a"),
+            (StatusCode::PERMANENT_REDIRECT, "/posts/a".to_owned())
+        );
+        assert_eq!(
+            redirect(&routing, "/s?q=rust"),
+            (
+                StatusCode::TEMPORARY_REDIRECT,
+                "/search?ref=old&q=rust".to_owned()
+            )
+        );
+        assert_eq!(
+            redirect(&routing, "/away?x"),
+            (StatusCode::FOUND, "https://example.com/?x".to_owned())
+        );
+    }
+
+    #[test]
+    fn rewrites_after_the_filesystem() {
+        let routing = routing(
+            r#"{
+                "functions": {"api/vercel.rs": {}},
+                "headers": [{"source": "/(.*)", "headers": [{"key": "x-frame-options", "value": "DENY"}]}],
+                "rewrites": [
+                    {"source": "/api/:path*", "destination": "/api/vercel.rs"},
+                    {"source": "/(.*)", "destination": "/index.html"}
+                ]
+            }"#,
+        );
+        let (file, headers) = route(&routing, "/app.js", &["/app.js"]);
+        assert_eq!(file.to_string(), "file /app.js");
+        assert_eq!(headers, [("x-frame-options".to_owned(), "DENY".to_owned())]);
+        assert_eq!(target(&routing, "/api/posts", &[]), "function");
+        assert_eq!(target(&routing, "/about", &[]), "file /index.html");
+    }
+
+    #[test]
+    fn invalid_configs() {
+        for json in [
+            r#"{"routes": [], "redirects": [{"source": "/a", "destination": "/b"}]}"#,
+            r#"{"redirects": [{"source": "/a", "destination": "/b", "statusCode": 200}]}"#,
+            r#"{"routes": [{"handle": "miss"}]}"#,
+            r#"{"routes": [{"dest": "/a"}]}"#,
+            r#"{"routes": [{"src": "/(a"}]}"#,
+        ] {
+            let parsed = serde_json::from_str(json).unwrap();
+            assert!(
+                Routing::compile(parsed, PathBuf::from(".")).is_err(),
+                "{json}"
+            );
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! Requests against the whole app, as `setup_app` builds it.
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use server::config::ConfigError;
+use server::{setup_app, Config, Opt, Shutdown};
+use tower::ServiceExt;
+
+/// A temporary static dir with an `index.html`, and an empty config file,
+/// for [`load`].
+fn site() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let static_dir = dir.path().join("dist");
+    std::fs::create_dir(&static_dir).unwrap();
+    std::fs::write(
+        static_dir.join("index.html"),
+        "<!DOCTYPE html><html><body><script type=\"module\">init()</script></body></html>",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("server.toml"), "").unwrap();
+    dir
+}
+
+/// The config of `site`, with `opt` over the defaults.
+fn load(site: &tempfile::TempDir, opt: Opt) -> Result<Config, ConfigError> {
+    Config::load(Opt {
+        config: Some(site.path().join("server.toml")),
+        static_dir: Some(site.path().join("dist")),
+        content_dir: Some(site.path().join("content")),
+        ..opt
+    })
+}
+
+/// The app over a fresh [`site`], which has to outlive it.
+async fn app(opt: Opt) -> (Router, tempfile::TempDir) {
+    let site = site();
+    let config = load(&site, opt).unwrap();
+    let app = setup_app(&config, &Shutdown::default()).await.unwrap();
+    (app, site)
+}
+
+async fn get(app: &Router, path: &str, headers: &[(&str, &str)]) -> Response {
+    let mut req = Request::get(path);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    app.clone()
+        .oneshot(req.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn no_cache_headers() {
+    let (app, _dir) = app(Opt {
+        no_cache_headers: Some(true),
+        ..Opt::default()
+    })
+    .await;
+    let res = get(&app, "/index.html", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CACHE_CONTROL).is_none());
+    assert!(res.headers().get(header::ETAG).is_none());
+}
+
+#[tokio::test]
+async fn index_with_csp_nonce_is_not_stored() {
+    let (app, _dir) = app(Opt::default()).await;
+    let first = get(&app, "/", &[]).await;
+    let second = get(&app, "/", &[]).await;
+    for res in [&first, &second] {
+        assert_eq!(res.status(), StatusCode::OK);
+        let csp = res.headers()[header::CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap();
+        assert!(csp.contains("'nonce-"), "{csp}");
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+        assert!(res.headers().get(header::ETAG).is_none());
+    }
+    assert_ne!(
+        first.headers()[header::CONTENT_SECURITY_POLICY],
+        second.headers()[header::CONTENT_SECURITY_POLICY]
+    );
+
+    // nothing to revalidate: a 304 would pair the stored body with the
+    // nonce of a new CSP
+    for path in ["/", "/index.html", "/posts/a"] {
+        let headers = [("if-none-match", "*"), ("accept", "text/html")];
+        let res = get(&app, path, &headers).await;
+        assert_eq!(res.status(), StatusCode::OK, "{path}");
+        assert!(res.headers().get(header::ETAG).is_none(), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn metrics_path_collisions() {
+    let site = site();
+    for path in [
+        "metrics",
+        "/feed.xml",
+        "/robots.txt",
+        "/",
+        "/api",
+        "/api/health",
+        "/metrics/{name}",
+    ] {
+        let opt = Opt {
+            metrics_path: Some(path.to_owned()),
+            ..Opt::default()
+        };
+        match load(&site, opt) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "metrics_path", "{path}"),
+            other => panic!("{path} was not rejected: {other:?}"),
+        }
+    }
+
+    let (app, _site) = app(Opt {
+        metrics_path: Some("/internal/metrics".to_owned()),
+        ..Opt::default()
+    })
+    .await;
+    let res = get(&app, "/internal/metrics", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+/// The paths of the routes of `app`. axum only lists them in the `Debug`
+/// output of a router.
+fn route_paths(app: &Router) -> Vec<String> {
+    let debug = format!("{app:?}");
+    let paths = regex::Regex::new(r#""(/[^"]*)""#).unwrap();
+    paths
+        .captures_iter(&debug)
+        .map(|captures| captures[1].to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn api_routes_are_documented() {
+    let site = site();
+    let (app, _site) = app(Opt {
+        admin_token: Some("secret".to_owned()),
+        contact_to: Some("Site <owner@example.com>".to_owned()),
+        contact_maildir: Some(site.path().join("mail")),
+        ..Opt::default()
+    })
+    .await;
+
+    let res = get(&app, "/api/openapi.json", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let doc: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let documented = doc["paths"].as_object().unwrap();
+
+    let routes = route_paths(&app);
+    // or the routes could no longer be read off the router
+    assert!(
+        routes.iter().any(|path| path == "/api/health"),
+        "{routes:?}"
+    );
+    for path in routes.iter().filter(|path| path.starts_with("/api")) {
+        assert!(documented.contains_key(path), "{path} is not documented");
+    }
+}
+
+#[tokio::test]
+async fn unknown_api_paths() {
+    let (app, _site) = app(Opt::default()).await;
+    for path in ["/api", "/api/nope", "/api/posts/a/b"] {
+        let res = get(&app, path, &[("accept", "text/html")]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{path}");
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/problem+json",
+            "{path}"
+        );
+    }
+    let req = Request::post("/api/nope").body(Body::empty()).unwrap();
+    let res = app.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+/// Routes behind the same error layers as in `setup_app`, one of them
+/// panicking.
+fn error_app() -> Router {
+    use axum::middleware;
+    use axum::routing::get;
+    use server::error;
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    fn panics() -> &'static str {
+        panic!("broken handler")
+    }
+    Router::new()
+        .route("/api/panic", get(|| async { panics() }))
+        .route("/panic", get(|| async { panics() }))
+        .route(
+            "/api/busy",
+            get(|| async {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [("retry-after", "5")],
+                    "Back <soon>",
+                )
+            }),
+        )
+        .route(
+            "/busy",
+            get(|| async {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [("retry-after", "5")],
+                    "Back <soon>",
+                )
+            }),
+        )
+        .route(
+            "/json",
+            get(|| async {
+                (
+                    StatusCode::BAD_REQUEST,
+                    axum::Json(serde_json::json!({"own": true})),
+                )
+            }),
+        )
+        .layer(CatchPanicLayer::custom(error::panic_response))
+        .layer(middleware::from_fn(error::pages))
+}
+
+async fn text(res: Response) -> String {
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body.into()).unwrap()
+}
+
+#[tokio::test]
+async fn panics_are_500s() {
+    let app = error_app();
+
+    let res = get(&app, "/api/panic", &[("accept", "text/html")]).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = serde_json::from_str(&text(res).await).unwrap();
+    assert_eq!(problem["status"], 500);
+    assert_eq!(problem["title"], "Internal Server Error");
+    // what panicked stays in the log
+    assert!(problem.get("detail").is_none(), "{problem}");
+
+    let res = get(&app, "/panic", &[("accept", "text/html")]).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(res.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let page = text(res).await;
+    assert!(
+        page.contains("<title>500 Internal Server Error</title>"),
+        "{page}"
+    );
+    assert!(!page.contains("broken handler"), "{page}");
+
+    // nothing to render for a script
+    let res = get(&app, "/panic", &[]).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(text(res).await.is_empty());
+}
+
+#[tokio::test]
+async fn error_bodies_fit_the_request() {
+    let app = error_app();
+    let html = [("accept", "text/html,application/xhtml+xml,*/*;q=0.8")];
+
+    // plain text becomes the detail, other headers stay
+    let res = get(&app, "/api/busy", &html).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    assert_eq!(res.headers()[header::RETRY_AFTER], "5");
+    let problem: serde_json::Value = serde_json::from_str(&text(res).await).unwrap();
+    assert_eq!(problem["detail"], "Back <soon>");
+
+    let res = get(&app, "/busy", &html).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()[header::RETRY_AFTER], "5");
+    assert!(res.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(text(res).await.contains("<p>Back &lt;soon&gt;</p>"));
+
+    // not a page load: as the handler left it
+    for accept in [&[][..], &[("accept", "application/json")]] {
+        let res = get(&app, "/busy", accept).await;
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(text(res).await, "Back <soon>");
+    }
+    let req = Request::post("/busy")
+        .header("accept", "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(res.headers().get(header::CONTENT_TYPE).is_none());
+
+    // a body of its own is kept
+    let res = get(&app, "/json", &html).await;
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    assert_eq!(text(res).await, r#"{"own":true}"#);
+}
+
+#[tokio::test]
+async fn missing_assets_get_the_error_page_on_page_loads() {
+    let (app, _site) = app(Opt::default()).await;
+    let res = get(&app, "/missing.js", &[("accept", "text/html")]).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(text(res).await.contains("There is nothing here."));
+
+    let res = get(&app, "/missing.js", &[("accept", "*/*")]).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get(header::CONTENT_TYPE).is_none());
+}
+
+#[tokio::test]
+async fn metrics_count_requests_by_route() {
+    let (app, _site) = app(Opt::default()).await;
+    let not_found = text(get(&app, "/api/posts/one", &[]).await).await.len();
+    get(&app, "/api/posts/two", &[]).await;
+    get(&app, "/api/health", &[]).await;
+    get(&app, "/index.html", &[]).await;
+    get(&app, "/some/page", &[("accept", "text/html")]).await;
+    get(&app, "/metrics", &[]).await;
+
+    let res = get(&app, "/metrics", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let metrics = text(res).await;
+    let lines: Vec<_> = metrics.lines().collect();
+    let has = |line: &str| lines.contains(&line);
+
+    // by the route template, not the path
+    let posts = r#"method="GET",route="/api/posts/{slug}",status="404""#;
+    assert!(
+        has(&format!("http_requests_total{{{posts}}} 2")),
+        "{metrics}"
+    );
+    assert!(!metrics.contains("/api/posts/one"), "{metrics}");
+    assert!(
+        has(r#"http_requests_total{method="GET",route="/api/health",status="200"} 1"#),
+        "{metrics}"
+    );
+    assert!(
+        has(r#"http_requests_total{method="GET",route="fallback",status="200"} 2"#),
+        "{metrics}"
+    );
+    // neither scrape counts
+    assert!(!metrics.contains(r#"route="/metrics""#), "{metrics}");
+
+    assert!(has("# TYPE http_request_duration_seconds histogram"));
+    assert!(
+        has(&format!(
+            "http_request_duration_seconds_bucket{{{posts},le=\"10\"}} 2"
+        )),
+        "{metrics}"
+    );
+    assert!(
+        has(&format!(
+            "http_request_duration_seconds_bucket{{{posts},le=\"+Inf\"}} 2"
+        )),
+        "{metrics}"
+    );
+    assert!(
+        has(&format!("http_request_duration_seconds_count{{{posts}}} 2")),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains(&format!("http_request_duration_seconds_sum{{{posts}}} ")),
+        "{metrics}"
+    );
+
+    // both problem bodies are under 100 bytes, and the buckets add up
+    let size = |le: &str| format!("http_response_size_bytes_bucket{{{posts},le=\"{le}\"}}");
+    assert!(has(&format!("{} 2", size("100"))), "{metrics}");
+    assert!(has(&format!("{} 2", size("1000"))), "{metrics}");
+    assert!(has(&format!("{} 2", size("10000000"))), "{metrics}");
+    assert!(has(&format!("{} 2", size("+Inf"))), "{metrics}");
+    assert!(
+        has(&format!("http_response_size_bytes_count{{{posts}}} 2")),
+        "{metrics}"
+    );
+    assert!(
+        has(&format!(
+            "http_response_size_bytes_sum{{{posts}}} {}",
+            2 * not_found
+        )),
+        "{metrics}"
+    );
+}
+
+/// Collects the `request_id` of every new span.
+#[derive(Clone, Default)]
+struct SpanIds(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanIds {
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        _id: &tracing::span::Id,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Visit<'a>(&'a SpanIds);
+        impl tracing::field::Visit for Visit<'_> {
+            fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+                if field.name() == "request_id" {
+                    self.0 .0.lock().unwrap().push(value.to_owned());
+                }
+            }
+            fn record_debug(&mut self, _: &tracing::field::Field, _: &dyn std::fmt::Debug) {}
+        }
+        attrs.record(&mut Visit(self));
+    }
+}
+
+/// Whether `id` looks like the ids `MakeRequestUuid` makes up.
+fn is_uuid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+#[tokio::test]
+async fn request_ids() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let spans = SpanIds::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+    let (app, _site) = app(Opt::default()).await;
+    let id = |res: &Response| res.headers()["x-request-id"].to_str().unwrap().to_owned();
+
+    let incoming = "a1b2c3d4-0000-4000-8000-abcdefabcdef";
+    let res = get(&app, "/api/health", &[("x-request-id", incoming)]).await;
+    assert_eq!(id(&res), incoming);
+    let from_proxy = "Root=1-67891233-abcdef012345678912345678";
+    let res = get(&app, "/", &[("x-request-id", from_proxy)]).await;
+    assert_eq!(id(&res), from_proxy);
+
+    let res = get(&app, "/api/health", &[]).await;
+    let generated = id(&res);
+    assert!(is_uuid(&generated), "{generated}");
+
+    let too_long = "a".repeat(129);
+    for invalid in ["", "two words", "<script>", "a\tb", &too_long] {
+        let res = get(&app, "/api/health", &[("x-request-id", invalid)]).await;
+        let replaced = id(&res);
+        assert_ne!(replaced, invalid);
+        assert!(is_uuid(&replaced), "{replaced}");
+    }
+    // more than one is as good as none
+    let req = Request::get("/api/health")
+        .header("x-request-id", "first")
+        .header("x-request-id", "second")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    let ids: Vec<_> = res.headers().get_all("x-request-id").iter().collect();
+    assert_eq!(ids.len(), 1);
+    assert!(is_uuid(ids[0].to_str().unwrap()));
+
+    let spans = spans.0.lock().unwrap();
+    assert_eq!(spans.len(), 9);
+    assert_eq!(spans[..3], [incoming, from_proxy, &generated]);
+    assert!(spans[3..].iter().all(|id| is_uuid(id)), "{spans:?}");
+}
+
+#[tokio::test]
+async fn post_paths_are_one_segment() {
+    assert_eq!(
+        shared::api::post_path("hello-world_2"),
+        "/api/posts/hello-world_2"
+    );
+    let (app, _site) = app(Opt::default()).await;
+    for slug in ["a/b", "a?b", "a#b", "a%2Fb", "../api", "ä b"] {
+        let path = shared::api::post_path(slug);
+        let res = get(&app, &path, &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{path}");
+        let problem: serde_json::Value = serde_json::from_str(&text(res).await).unwrap();
+        // answered by the post route, with the slug as it was
+        assert_eq!(
+            problem["detail"],
+            format!("There is no post named {slug}."),
+            "{path}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn contact_page_is_only_in_the_sitemap_with_a_form() {
+    let sitemap = |app: Router| async move { text(get(&app, "/sitemap.xml", &[]).await).await };
+
+    let (plain, _site) = app(Opt::default()).await;
+    let without = sitemap(plain).await;
+    assert!(
+        without.contains("<loc>http://localhost:8080/</loc>"),
+        "{without}"
+    );
+    assert!(!without.contains("/contact<"), "{without}");
+
+    let maildir = tempfile::tempdir().unwrap();
+    let (with_form, _other_site) = app(Opt {
+        contact_to: Some("owner@example.com".to_owned()),
+        contact_maildir: Some(maildir.path().to_owned()),
+        ..Opt::default()
+    })
+    .await;
+    let with = sitemap(with_form).await;
+    assert!(
+        with.contains("<loc>http://localhost:8080/contact</loc>"),
+        "{with}"
+    );
+}
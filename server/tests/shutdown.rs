@@ -0,0 +1,106 @@
+//! Draining a real server on shutdown.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::routing::get;
+use axum::Router;
+use server::Shutdown;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+
+/// Serve a `/slow` route that takes `delay` on a free port, draining for up
+/// to `timeout` once `shutdown` triggers. Returns a client whose request to
+/// `/slow` has started, and the task running `drain`.
+async fn serve_slow(
+    shutdown: &Shutdown,
+    delay: Duration,
+    timeout: Duration,
+) -> (
+    TcpStream,
+    tokio::task::JoinHandle<Option<std::io::Result<()>>>,
+) {
+    let started = Arc::new(Notify::new());
+    let app = Router::new().route(
+        "/slow",
+        get({
+            let started = started.clone();
+            move || async move {
+                started.notify_one();
+                tokio::time::sleep(delay).await;
+                "done"
+            }
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.triggered());
+    let drain = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { shutdown.drain(server, timeout).await }
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    started.notified().await;
+    (client, drain)
+}
+
+fn hook_flag(shutdown: &Shutdown) -> Arc<AtomicBool> {
+    let ran = Arc::new(AtomicBool::new(false));
+    shutdown.on_shutdown("test", {
+        let ran = ran.clone();
+        || async move { ran.store(true, Ordering::SeqCst) }
+    });
+    ran
+}
+
+#[tokio::test]
+async fn in_flight_request_completes() {
+    let shutdown = Shutdown::default();
+    let hook_ran = hook_flag(&shutdown);
+    let (mut client, drain) = serve_slow(
+        &shutdown,
+        Duration::from_millis(300),
+        Duration::from_secs(10),
+    )
+    .await;
+
+    shutdown.trigger();
+    let mut response = String::new();
+    client.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(response.ends_with("done"), "{response}");
+
+    let output = drain.await.unwrap();
+    assert!(matches!(output, Some(Ok(()))));
+    assert!(hook_ran.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn drain_timeout_cuts_off_slow_request() {
+    let shutdown = Shutdown::default();
+    let hook_ran = hook_flag(&shutdown);
+    let (_client, drain) = serve_slow(
+        &shutdown,
+        Duration::from_secs(60),
+        Duration::from_millis(200),
+    )
+    .await;
+
+    let triggered_at = Instant::now();
+    shutdown.trigger();
+    let output = drain.await.unwrap();
+    assert!(output.is_none(), "the server finished draining");
+    let elapsed = triggered_at.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(200) && elapsed < Duration::from_secs(5),
+        "{elapsed:?}"
+    );
+    assert!(hook_ran.load(Ordering::SeqCst));
+}
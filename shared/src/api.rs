@@ -0,0 +1,142 @@
+//! Request and response bodies of the `/api` routes.
+//!
+//! The server serializes exactly these types and the frontend deserializes
+//! them, so changing the shape of a response breaks the build of both
+//! instead of the page at runtime. With the `utoipa` feature they also
+//! describe themselves for the server's OpenAPI document.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+
+/// Everything but the unreserved characters of RFC 3986, so a value stays
+/// one path segment.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// The path of `GET /api/posts/{slug}`, whatever `slug` contains.
+pub fn post_path(slug: &str) -> String {
+    format!("/api/posts/{}", utf8_percent_encode(slug, SEGMENT))
+}
+
+/// `GET /api/hello/`
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Hello {
+    pub message: String,
+}
+
+/// Everything about a post except its body. `GET /api/posts` answers all
+/// of them, newest first.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PostMeta {
+    pub slug: String,
+    pub title: String,
+    pub date: NaiveDate,
+    pub tags: Vec<String>,
+}
+
+/// A post with its body rendered to HTML. `GET /api/posts/{slug}`
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Post {
+    #[serde(flatten)]
+    pub meta: PostMeta,
+    pub html: String,
+}
+
+/// `POST /api/contact`, as JSON or as an urlencoded form.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ContactRequest {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub message: String,
+    /// a honeypot, to be hidden from people and left empty
+    #[serde(default)]
+    pub website: String,
+    /// the token of `GET /api/contact-token`, fetched when the form was shown
+    #[serde(default)]
+    pub form_token: String,
+}
+
+/// `GET /api/contact-token`, which proves when a contact form was shown.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContactToken {
+    pub token: String,
+}
+
+/// The answer to a [`ContactRequest`] that was taken.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContactSent {
+    pub sent: bool,
+}
+
+/// The answer to an invalid [`ContactRequest`], with status 422.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContactInvalid {
+    #[serde(flatten)]
+    pub problem: Problem,
+    /// messages for people, keyed by field name
+    pub errors: BTreeMap<String, String>,
+}
+
+/// `GET /api/health`
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Health {
+    pub status: String,
+}
+
+/// `GET /api/ready`, with status 503 unless `ready`.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ready {
+    pub ready: bool,
+    /// `ok` or why not, keyed by check
+    pub checks: BTreeMap<String, String>,
+}
+
+/// `GET /api/version`
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Version {
+    pub git_sha: String,
+    /// RFC 3339
+    pub built_at: String,
+    pub server: String,
+    pub shared: String,
+}
+
+/// RFC 9457 problem details, the `application/problem+json` body of every
+/// failed `/api` request.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// a URI naming the kind of problem, `about:blank` when the status says
+    /// it all
+    #[serde(rename = "type", default = "about_blank")]
+    pub kind: String,
+    /// the reason phrase of the status
+    pub title: String,
+    pub status: u16,
+    /// what went wrong this time, for people
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+fn about_blank() -> String {
+    "about:blank".to_owned()
+}
@@ -0,0 +1,7 @@
+//! Definitions shared by the server and the frontend.
+
+pub mod api;
+pub mod routes;
+
+/// The version of this crate, so the server can report what it was built with.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
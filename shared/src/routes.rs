@@ -0,0 +1,57 @@
+//! The pages of the frontend.
+//!
+//! The frontend routes with [`Route`] (behind the `yew-router` feature) and
+//! the server lists the same paths in its sitemap, so both are generated
+//! from the single table at the bottom of this file.
+
+macro_rules! routes {
+    ($($(#[$attr:meta])* $variant:ident $({ $($field:ident),* })? => $path:tt,)*) => {
+        #[derive(Clone, Debug, PartialEq)]
+        #[cfg_attr(feature = "yew-router", derive(yew_router::Routable))]
+        pub enum Route {
+            $(
+                $(#[$attr])*
+                #[cfg_attr(feature = "yew-router", at($path))]
+                $variant $({ $($field: String),* })?,
+            )*
+        }
+
+        impl Route {
+            /// The path pattern of every route, with `:field` placeholders.
+            pub const PATTERNS: &'static [&'static str] = &[$($path),*];
+
+            /// The concrete path of this route.
+            pub fn path(&self) -> String {
+                match self {
+                    $(
+                        Route::$variant $({ $($field),* })? => {
+                            #[allow(unused_mut)]
+                            let mut path = String::from($path);
+                            $($(path = path.replace(concat!(":", stringify!($field)), $field);)*)?
+                            path
+                        }
+                    )*
+                }
+            }
+        }
+    };
+}
+
+routes! {
+    Home => "/",
+    HelloServer => "/hello-server",
+    /// The contact form, posting to `/api/contact`.
+    Contact => "/contact",
+    /// A post rendered from the content directory.
+    Post { slug } => "/posts/:slug",
+}
+
+impl Route {
+    /// Paths of the routes without placeholders, which exist exactly once.
+    pub fn static_paths() -> impl Iterator<Item = &'static str> {
+        Self::PATTERNS
+            .iter()
+            .copied()
+            .filter(|pattern| !pattern.contains(':'))
+    }
+}
@@ -13,6 +13,12 @@ rm -rf server/dist
 mkdir -p server/dist
 mv dist server
 
+echo "Copying posts to server/content/."
+rm -rf server/content
+if [ -d content ]; then
+    cp -r content server/content
+fi
+
 echo "Escape server/dist/ from gitignore in root."
 touch server/.gitignore
 echo "!dist/" >> server/.gitignore